@@ -1,97 +1,464 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+use std::fmt;
 
-fn check_in_range(i: i32, j: i32, m: i32) -> bool {
-    i >= 0 && i < m && j >= 0 && j < m
+fn check_in_range(grid: &[Vec<char>], (i, j): (i32, i32)) -> bool {
+    i >= 0 && j >= 0 && (i as usize) < grid.len() && (j as usize) < grid[i as usize].len()
 }
 
 fn insert_into_adj(
-    adj_list: &mut HashMap<(i32, i32), Vec<(i32, i32)>>,
+    adj_list: &mut AdjList,
+    grid: &[Vec<char>],
     (i, j): (i32, i32),
     (k, l): (i32, i32),
     (x, y): (i32, i32),
-    m: i32,
 ) {
-    if check_in_range(i, j, m) && check_in_range(k, l, m) {
-        adj_list.entry((x, y)).or_insert(Vec::new()).push((i, j));
-        adj_list.entry((x, y)).or_insert(Vec::new()).push((k, l));
+    if check_in_range(grid, (i, j)) && check_in_range(grid, (k, l)) {
+        adj_list.entry((x, y)).or_default().push((i, j));
+        adj_list.entry((x, y)).or_default().push((k, l));
     }
 }
 
-fn parse_lines(lines: &Vec<String>) -> ((i32, i32), HashMap<(i32, i32), Vec<(i32, i32)>>) {
+const PIPES: [char; 6] = ['|', '-', 'L', 'J', '7', 'F'];
+
+// offsets of the two cells a pipe connects
+fn pipe_ends(ch: char) -> Option<[(i32, i32); 2]> {
+    match ch {
+        '|' => Some([(-1, 0), (1, 0)]),
+        '-' => Some([(0, -1), (0, 1)]),
+        'L' => Some([(-1, 0), (0, 1)]),
+        'J' => Some([(-1, 0), (0, -1)]),
+        '7' => Some([(1, 0), (0, -1)]),
+        'F' => Some([(1, 0), (0, 1)]),
+        _ => None,
+    }
+}
+
+fn grid_at(grid: &[Vec<char>], (i, j): (i32, i32)) -> char {
+    if i < 0 || j < 0 {
+        return '.';
+    }
+    *grid
+        .get(i as usize)
+        .and_then(|row| row.get(j as usize))
+        .unwrap_or(&'.')
+}
+
+// follows the pipes leaving S through `shape` and checks they come back through its other end
+fn closes_loop(grid: &[Vec<char>], s_idx: (i32, i32), shape: char) -> bool {
+    let [(di, dj), (back_i, back_j)] = pipe_ends(shape).unwrap();
+    let arrive_from = (s_idx.0 + back_i, s_idx.1 + back_j);
+
+    let mut prev = s_idx;
+    let mut cur = (s_idx.0 + di, s_idx.1 + dj);
+
+    loop {
+        if cur == s_idx {
+            return prev == arrive_from;
+        }
+
+        let Some(ends) = pipe_ends(grid_at(grid, cur)) else {
+            return false;
+        };
+        let neighbours = ends.map(|(di, dj)| (cur.0 + di, cur.1 + dj));
+        let next = match neighbours {
+            [a, b] if a == prev => b,
+            [a, b] if b == prev => a,
+            _ => return false,
+        };
+
+        prev = cur;
+        cur = next;
+    }
+}
+
+fn infer_start(grid: &[Vec<char>], s_idx: (i32, i32)) -> Result<char, String> {
+    let fits: Vec<char> = PIPES
+        .iter()
+        .copied()
+        .filter(|&shape| closes_loop(grid, s_idx, shape))
+        .collect();
+
+    match fits[..] {
+        [shape] => Ok(shape),
+        [] => Err(format!("no pipe shape at S {:?} closes a loop", s_idx)),
+        _ => Err(format!("S {:?} could be any of {:?}", s_idx, fits)),
+    }
+}
+
+type AdjList = HashMap<(i32, i32), Vec<(i32, i32)>>;
+
+#[derive(Clone, Debug)]
+struct Maze {
+    // rows may differ in length; S is replaced by the pipe it stands for
+    grid: Vec<Vec<char>>,
+    s_idx: (i32, i32),
+    adj_list: AdjList,
+}
+
+fn parse_lines(lines: &[String]) -> Result<Maze, String> {
+    let mut grid: Vec<Vec<char>> = lines.iter().map(|line| line.chars().collect()).collect();
+
+    let starts: Vec<(i32, i32)> = (0..grid.len())
+        .flat_map(|i| (0..grid[i].len()).map(move |j| (i, j)))
+        .filter(|&(i, j)| grid[i][j] == 'S')
+        .map(|(i, j)| (i as i32, j as i32))
+        .collect();
+    let s_idx = match starts[..] {
+        [s_idx] => s_idx,
+        _ => return Err(format!("expected one S, found {}", starts.len())),
+    };
+    grid[s_idx.0 as usize][s_idx.1 as usize] = infer_start(&grid, s_idx)?;
+
     let mut adj_list = HashMap::new();
-    let mut s_idx = (0, 0);
 
-    for (i, line) in lines.iter().enumerate() {
-        let m = lines[i].len() as i32;
-        for (j, ch) in line.chars().enumerate() {
+    for (i, row) in grid.iter().enumerate() {
+        for (j, &ch) in row.iter().enumerate() {
             let (ii, jj) = (i as i32, j as i32);
-            match ch {
-                '|' => insert_into_adj(&mut adj_list, (ii + 1, jj), (ii - 1, jj), (ii, jj), m),
-                '-' => insert_into_adj(&mut adj_list, (ii, jj + 1), (ii, jj - 1), (ii, jj), m),
-                'L' => insert_into_adj(&mut adj_list, (ii - 1, jj), (ii, jj + 1), (ii, jj), m),
-                'J' => insert_into_adj(&mut adj_list, (ii - 1, jj), (ii, jj - 1), (ii, jj), m),
-                '7' => insert_into_adj(&mut adj_list, (ii + 1, jj), (ii, jj - 1), (ii, jj), m),
-                'F' => insert_into_adj(&mut adj_list, (ii + 1, jj), (ii, jj + 1), (ii, jj), m),
-                'S' => {
-                    // hack - treat S as | to keep the cycle
-                    insert_into_adj(&mut adj_list, (ii + 1, jj), (ii - 1, jj), (ii, jj), m);
-                    s_idx = (ii, jj);
-                }
-                '.' => {}
-                _ => panic!("Invalid character in input"),
+            match pipe_ends(ch) {
+                Some([(a, b), (c, d)]) => insert_into_adj(
+                    &mut adj_list,
+                    &grid,
+                    (ii + a, jj + b),
+                    (ii + c, jj + d),
+                    (ii, jj),
+                ),
+                None if ch == '.' => {}
+                None => return Err(format!("Invalid character {} in input", ch)),
             }
         }
     }
 
-    (s_idx, adj_list)
+    Ok(Maze {
+        grid,
+        s_idx,
+        adj_list,
+    })
 }
 
-fn process_polygon(
-    adj_list: &HashMap<(i32, i32), Vec<(i32, i32)>>,
-    s_idx: (i32, i32),
-) -> (i32, i32, i32) {
-    // Pick's theorem: A = i + b/2 - 1 where A is area, i is number of interior points and b is number of boundary points
-    let mut boundary = 0;
-    let mut area = 0;
-
-    let mut cur = s_idx.clone();
-    let mut vis: HashSet<(i32, i32)> = HashSet::new();
+// cells of the loop through S in walking order, starting at S
+fn trace_loop(maze: &Maze) -> Result<Vec<(i32, i32)>, String> {
+    let mut cells: Vec<(i32, i32)> = Vec::new();
+    let mut cur = maze.s_idx;
+    let mut prev = maze.s_idx;
 
     // do-while hack
     while {
-        for &(i, j) in adj_list
+        cells.push(cur);
+        let adjacent = maze
+            .adj_list
             .get(&cur)
-            .unwrap_or_else(|| panic!("expected adjacent elements to: {:?}", cur))
-        {
-            if !vis.contains(&(i, j)) {
-                boundary += 1;
-                vis.insert((i, j));
-
-                // Shoelace formula where area = 1/2 * (x0*y1 - x1*y0 + ...)
-                area += cur.0 * j - cur.1 * i;
-
+            .ok_or_else(|| format!("expected adjacent elements to: {:?}", cur))?;
+        for &(i, j) in adjacent {
+            if (i, j) != prev {
+                prev = cur;
                 cur = (i, j);
                 break;
             }
         }
-        cur != s_idx
+        cur != maze.s_idx
     } {}
 
-    // Complete Shoelace formula
-    area = area / 2;
+    Ok(cells)
+}
+
+fn process_polygon(cells: &[(i32, i32)]) -> (i32, i32, i32) {
+    // Pick's theorem: A = i + b/2 - 1 where A is area, i is number of interior points and b is number of boundary points
+    let boundary = cells.len() as i32;
+    let mut area = 0;
+
+    for (k, &(x, y)) in cells.iter().enumerate() {
+        let (i, j) = cells[(k + 1) % cells.len()];
+
+        // Shoelace formula where area = 1/2 * (x0*y1 - x1*y0 + ...)
+        area += x * j - y * i;
+    }
+
+    // Complete Shoelace formula, the sign depends on which way the loop was walked
+    area = area.abs() / 2;
 
     // Pick's theorem to find interior points
     let interior_points = area - boundary / 2 + 1;
     (area, interior_points, boundary)
 }
 
-pub fn p1(lines: &Vec<String>) -> i32 {
-    let (s_idx, adj_list) = parse_lines(lines);
-    let boundary = process_polygon(&adj_list, s_idx).2;
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MainLoop {
+    // corner cells of the loop in walking order, starting from S's side of the loop
+    pub vertices: Vec<(i32, i32)>,
+    pub enclosed: Vec<(i32, i32)>,
+    pub farthest: (i32, i32),
+    pub distance: usize,
+}
+
+// a cell is inside when a ray to its left crosses the loop an odd number of times; only pipes
+// with a northern end count as crossings so that runs like L--7 are counted once
+fn enclosed_cells(maze: &Maze, on_loop: &[Vec<bool>]) -> Vec<(i32, i32)> {
+    let mut enclosed: Vec<(i32, i32)> = Vec::new();
+
+    for (i, row) in maze.grid.iter().enumerate() {
+        let mut inside = false;
+        for (j, &ch) in row.iter().enumerate() {
+            if on_loop[i][j] {
+                if matches!(ch, '|' | 'L' | 'J') {
+                    inside = !inside;
+                }
+            } else if inside {
+                enclosed.push((i as i32, j as i32));
+            }
+        }
+    }
+
+    enclosed
+}
+
+fn loop_mask(maze: &Maze, cells: &[(i32, i32)]) -> Vec<Vec<bool>> {
+    let mut on_loop: Vec<Vec<bool>> = maze.grid.iter().map(|row| vec![false; row.len()]).collect();
+    for &(i, j) in cells {
+        on_loop[i as usize][j as usize] = true;
+    }
+    on_loop
+}
+
+pub fn main_loop(lines: &[String]) -> Result<MainLoop, String> {
+    let maze = parse_lines(lines)?;
+    let cells = trace_loop(&maze)?;
+    let on_loop = loop_mask(&maze, &cells);
+
+    Ok(MainLoop {
+        vertices: cells
+            .iter()
+            .copied()
+            .filter(|&(i, j)| matches!(maze.grid[i as usize][j as usize], 'L' | 'J' | '7' | 'F'))
+            .collect(),
+        enclosed: enclosed_cells(&maze, &on_loop),
+        farthest: cells[cells.len() / 2],
+        distance: cells.len() / 2,
+    })
+}
+
+// the maze with loop cells kept, and every other cell marked I(nside) or O(utside)
+pub fn render(lines: &[String]) -> Result<String, String> {
+    let maze = parse_lines(lines)?;
+    let on_loop = loop_mask(&maze, &trace_loop(&maze)?);
+    let enclosed = enclosed_cells(&maze, &on_loop);
+
+    let mut out: Vec<Vec<char>> = maze
+        .grid
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            row.iter()
+                .enumerate()
+                .map(|(j, &ch)| if on_loop[i][j] { ch } else { 'O' })
+                .collect()
+        })
+        .collect();
+    for (i, j) in enclosed {
+        out[i as usize][j as usize] = 'I';
+    }
+
+    Ok(out
+        .iter()
+        .map(|row| row.iter().collect::<String>() + "\n")
+        .collect())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Enclosure {
+    pub area: i32,
+    pub interior: i32,
+    pub boundary: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Component {
+    // sorted by row, then column
+    pub cells: Vec<(i32, i32)>,
+    // (cell, cell its pipe points at) for every end that isn't met by a pipe pointing back
+    pub dangling: Vec<((i32, i32), (i32, i32))>,
+    pub contains_start: bool,
+    // only for components that form a single closed loop
+    pub enclosure: Option<Enclosure>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MazeReport {
+    pub start: Option<(i32, i32)>,
+    // pipes that point back at S
+    pub start_links: usize,
+    // largest first
+    pub components: Vec<Component>,
+}
+
+impl MazeReport {
+    pub fn loops(&self) -> impl Iterator<Item = &Component> {
+        self.components.iter().filter(|c| c.enclosure.is_some())
+    }
+}
+
+impl fmt::Display for MazeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.start {
+            Some(start) => writeln!(f, "S at {:?}", start)?,
+            None => writeln!(f, "no S")?,
+        }
+        writeln!(
+            f,
+            "{} pipe components, {} closed loops",
+            self.components.len(),
+            self.loops().count()
+        )?;
+
+        for component in self.components.iter() {
+            write!(
+                f,
+                "  {} cells from {:?}{}",
+                component.cells.len(),
+                component.cells[0],
+                if component.contains_start {
+                    " (contains S)"
+                } else {
+                    ""
+                }
+            )?;
+            match component.enclosure {
+                Some(e) => writeln!(f, ": loop, area {}, {} enclosed", e.area, e.interior)?,
+                // S has no loose ends of its own, so this is an S joined to no pipe, or to more
+                // than two
+                None if component.dangling.is_empty() => match self.start_links {
+                    0 => writeln!(f, ": S links to no pipe")?,
+                    1 => writeln!(f, ": dead end at S")?,
+                    n => writeln!(f, ": branches at S into {} pipes", n)?,
+                },
+                None => writeln!(f, ": {} dangling ends", component.dangling.len())?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// S has no shape of its own here, it links to every neighbour whose pipe points at it
+fn cell_ends(grid: &[Vec<char>], (i, j): (i32, i32)) -> Vec<(i32, i32)> {
+    match grid_at(grid, (i, j)) {
+        'S' => [(-1, 0), (1, 0), (0, -1), (0, 1)]
+            .iter()
+            .map(|&(di, dj)| (i + di, j + dj))
+            .filter(|&next| {
+                pipe_ends(grid_at(grid, next))
+                    .is_some_and(|ends| ends.contains(&(i - next.0, j - next.1)))
+            })
+            .collect(),
+        ch => pipe_ends(ch).map_or(vec![], |ends| {
+            ends.map(|(di, dj)| (i + di, j + dj)).to_vec()
+        }),
+    }
+}
+
+// every pipe is a node and two pipes are linked when both point at each other
+pub fn analyze(lines: &[String]) -> Result<MazeReport, String> {
+    let grid: Vec<Vec<char>> = lines.iter().map(|line| line.chars().collect()).collect();
+    let mut start = None;
+
+    for (i, row) in grid.iter().enumerate() {
+        for (j, &ch) in row.iter().enumerate() {
+            match ch {
+                'S' if start.is_some() => return Err("more than one S".to_string()),
+                'S' => start = Some((i as i32, j as i32)),
+                '.' => {}
+                ch if pipe_ends(ch).is_none() => {
+                    return Err(format!("Invalid character {} in input", ch))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // (ends met by a pipe pointing back, ends that aren't)
+    let links = |cell: (i32, i32)| {
+        let (linked, loose): (Vec<_>, Vec<_>) = cell_ends(&grid, cell)
+            .into_iter()
+            .partition(|&next| cell_ends(&grid, next).contains(&cell));
+        (linked, loose)
+    };
+
+    let mut seen: Vec<Vec<bool>> = grid.iter().map(|row| vec![false; row.len()]).collect();
+    let mut components: Vec<Component> = Vec::new();
+
+    for (i, row) in grid.iter().enumerate() {
+        for (j, &ch) in row.iter().enumerate() {
+            if ch == '.' || seen[i][j] {
+                continue;
+            }
+
+            let mut cells: Vec<(i32, i32)> = Vec::new();
+            let mut dangling: Vec<((i32, i32), (i32, i32))> = Vec::new();
+            let mut degrees_ok = true;
+            let mut stack = vec![(i as i32, j as i32)];
+            seen[i][j] = true;
+
+            while let Some(cell) = stack.pop() {
+                cells.push(cell);
+                let (linked, loose) = links(cell);
+                degrees_ok &= linked.len() == 2;
+                dangling.extend(loose.into_iter().map(|next| (cell, next)));
+
+                for next in linked {
+                    let (ni, nj) = (next.0 as usize, next.1 as usize);
+                    if !seen[ni][nj] {
+                        seen[ni][nj] = true;
+                        stack.push(next);
+                    }
+                }
+            }
+
+            cells.sort_unstable();
+            dangling.sort_unstable();
+
+            // connected and every cell has two links, so walking from any cell goes round once
+            let enclosure = (degrees_ok && dangling.is_empty()).then(|| {
+                let mut walk = vec![cells[0]];
+                let mut prev = cells[0];
+                let mut cur = links(cells[0]).0[0];
+                while cur != cells[0] {
+                    walk.push(cur);
+                    let next = links(cur).0.into_iter().find(|&n| n != prev).unwrap();
+                    prev = cur;
+                    cur = next;
+                }
+
+                let (area, interior, boundary) = process_polygon(&walk);
+                Enclosure {
+                    area,
+                    interior,
+                    boundary,
+                }
+            });
+
+            components.push(Component {
+                contains_start: start.is_some_and(|s| cells.binary_search(&s).is_ok()),
+                cells,
+                dangling,
+                enclosure,
+            });
+        }
+    }
+
+    components.sort_by_key(|c| std::cmp::Reverse(c.cells.len()));
+    Ok(MazeReport {
+        start,
+        start_links: start.map_or(0, |s| links(s).0.len()),
+        components,
+    })
+}
+
+pub fn p1(lines: &[String]) -> i32 {
+    let maze = parse_lines(lines).unwrap_or_else(|e| panic!("{}", e));
+    let boundary = process_polygon(&trace_loop(&maze).unwrap_or_else(|e| panic!("{}", e))).2;
     boundary / 2
 }
 
-pub fn p2(lines: &Vec<String>) -> i32 {
-    let (s_idx, adj_list) = parse_lines(lines);
-    process_polygon(&adj_list, s_idx).1
+pub fn p2(lines: &[String]) -> i32 {
+    let maze = parse_lines(lines).unwrap_or_else(|e| panic!("{}", e));
+    process_polygon(&trace_loop(&maze).unwrap_or_else(|e| panic!("{}", e))).1
 }
@@ -1,10 +1,10 @@
-use std::collections::HashMap;
+use std::collections::BinaryHeap;
 
-fn parse_lines(lines: &Vec<String>) -> Vec<Vec<char>> {
+fn parse_lines(lines: &[String]) -> Vec<Vec<char>> {
     lines.iter().map(|line| line.chars().collect()).collect()
 }
 
-fn get_all_empty_rows_cols(universe: &Vec<Vec<char>>) -> (Vec<usize>, Vec<usize>) {
+fn get_all_empty_rows_cols(universe: &[Vec<char>]) -> (Vec<usize>, Vec<usize>) {
     let mut empty_rows = Vec::new();
     let mut empty_cols = Vec::new();
 
@@ -23,18 +23,62 @@ fn get_all_empty_rows_cols(universe: &Vec<Vec<char>>) -> (Vec<usize>, Vec<usize>
     (empty_rows, empty_cols)
 }
 
-fn get_all_galaxies(universe: &Vec<Vec<char>>, replace_empty_by: usize) -> Vec<(usize, usize)> {
+// how many rows or columns every empty row or column turns into, at least 1 each
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Expansion {
+    rows: u128,
+    cols: u128,
+}
+
+impl Expansion {
+    pub fn new(rows: u128, cols: u128) -> Result<Expansion, String> {
+        if rows == 0 || cols == 0 {
+            return Err(format!(
+                "empty rows and columns have to be replaced by at least 1, not {} and {}",
+                rows, cols
+            ));
+        }
+        Ok(Expansion { rows, cols })
+    }
+
+    pub fn uniform(replace_empty_by: u128) -> Result<Expansion, String> {
+        Expansion::new(replace_empty_by, replace_empty_by)
+    }
+
+    pub fn rows(&self) -> u128 {
+        self.rows
+    }
+
+    pub fn cols(&self) -> u128 {
+        self.cols
+    }
+}
+
+// shifted[k] is where index k ends up once every earlier empty index is replaced by `factor`
+fn shifted_indices(len: usize, empty: &[usize], factor: u128) -> Vec<u128> {
+    let mut shifted = Vec::with_capacity(len);
+    let mut empty_before: u128 = 0;
+
+    for k in 0..len {
+        shifted.push(k as u128 + (factor - 1) * empty_before);
+        if empty.binary_search(&k).is_ok() {
+            empty_before += 1;
+        }
+    }
+
+    shifted
+}
+
+fn get_all_galaxies(universe: &[Vec<char>], expansion: Expansion) -> Vec<(u128, u128)> {
     let mut galaxies = Vec::new();
     let (empty_rows, empty_cols) = get_all_empty_rows_cols(universe);
+    let shifted_rows = shifted_indices(universe.len(), &empty_rows, expansion.rows);
+    let shifted_cols = shifted_indices(universe[0].len(), &empty_cols, expansion.cols);
 
     for (i, row) in universe.iter().enumerate() {
         for (j, &c) in row.iter().enumerate() {
             if c == '#' {
-                let shifted_i =
-                    i + ((replace_empty_by - 1) * empty_rows.iter().filter(|&&x| x < i).count());
-                let shifted_j =
-                    j + ((replace_empty_by - 1) * empty_cols.iter().filter(|&&x| x < j).count());
-                galaxies.push((shifted_i, shifted_j));
+                galaxies.push((shifted_rows[i], shifted_cols[j]));
             }
         }
     }
@@ -42,30 +86,124 @@ fn get_all_galaxies(universe: &Vec<Vec<char>>, replace_empty_by: usize) -> Vec<(
     galaxies
 }
 
-fn get_all_pairwise_distances(galaxies: &Vec<(usize, usize)>) -> HashMap<(usize, usize), usize> {
-    let mut distances: HashMap<(usize, usize), usize> = HashMap::new();
+// sum of |a - b| over every pair: once sorted, the k-th value is at least each of the k before it
+fn axis_distance_sum(mut coords: Vec<u128>) -> u128 {
+    coords.sort_unstable();
 
-    for i in 0..galaxies.len() {
-        for j in i + 1..galaxies.len() {
-            let galaxy1 = galaxies[i];
-            let galaxy2 = galaxies[j];
+    let mut prefix: u128 = 0;
+    let mut total: u128 = 0;
+    for (k, &x) in coords.iter().enumerate() {
+        total += x * k as u128 - prefix;
+        prefix += x;
+    }
+
+    total
+}
 
-            let distance = (galaxy1.0).abs_diff(galaxy2.0) + (galaxy1.1).abs_diff(galaxy2.1);
-            distances.insert((i + 1, j + 1), distance);
+// manhattan distances split by axis, so this is O(n log n) in the number of galaxies
+pub fn sum_of_distances(galaxies: &[(u128, u128)]) -> u128 {
+    axis_distance_sum(galaxies.iter().map(|g| g.0).collect())
+        + axis_distance_sum(galaxies.iter().map(|g| g.1).collect())
+}
+
+// galaxies numbered from 1 in reading order, as in the puzzle text
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Galaxies {
+    pub coords: Vec<(u128, u128)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Neighbours {
+    pub galaxy: usize,
+    // (galaxy, distance), ties go to the lower number
+    pub nearest: (usize, u128),
+    pub farthest: (usize, u128),
+}
+
+impl Galaxies {
+    pub fn new(lines: &[String], expansion: Expansion) -> Galaxies {
+        Galaxies {
+            coords: get_all_galaxies(&parse_lines(lines), expansion),
         }
     }
 
-    distances
+    fn coord(&self, galaxy: usize) -> (u128, u128) {
+        assert!(
+            (1..=self.coords.len()).contains(&galaxy),
+            "there is no galaxy {}",
+            galaxy
+        );
+        self.coords[galaxy - 1]
+    }
+
+    pub fn distance(&self, galaxy1: usize, galaxy2: usize) -> u128 {
+        let (a, b) = (self.coord(galaxy1), self.coord(galaxy2));
+        a.0.abs_diff(b.0) + a.1.abs_diff(b.1)
+    }
+
+    // None when the galaxy is alone
+    pub fn neighbours(&self, galaxy: usize) -> Option<Neighbours> {
+        let mut others = (1..=self.coords.len())
+            .filter(|&other| other != galaxy)
+            .map(|other| (other, self.distance(galaxy, other)));
+        let first = others.next()?;
+
+        let (nearest, farthest) = others.fold((first, first), |(near, far), (other, dist)| {
+            (
+                if dist < near.1 { (other, dist) } else { near },
+                if dist > far.1 { (other, dist) } else { far },
+            )
+        });
+
+        Some(Neighbours {
+            galaxy,
+            nearest,
+            farthest,
+        })
+    }
+
+    pub fn all_neighbours(&self) -> Vec<Neighbours> {
+        (1..=self.coords.len())
+            .filter_map(|galaxy| self.neighbours(galaxy))
+            .collect()
+    }
+
+    // (distance, galaxy1, galaxy2) with galaxy1 < galaxy2, closest first; keeps at most k
+    // pairs in a max-heap instead of collecting every pair
+    pub fn closest_pairs(&self, k: usize) -> Vec<(u128, usize, usize)> {
+        if k == 0 {
+            return vec![];
+        }
+        let mut heap: BinaryHeap<(u128, usize, usize)> = BinaryHeap::with_capacity(k);
+
+        for i in 1..=self.coords.len() {
+            for j in i + 1..=self.coords.len() {
+                let pair = (self.distance(i, j), i, j);
+                if heap.len() < k {
+                    heap.push(pair);
+                } else if pair < *heap.peek().unwrap() {
+                    heap.pop();
+                    heap.push(pair);
+                }
+            }
+        }
+
+        heap.into_sorted_vec()
+    }
+
+    pub fn sum_of_distances(&self) -> u128 {
+        sum_of_distances(&self.coords)
+    }
+}
+
+pub fn solve(lines: &[String], expansion: Expansion) -> u128 {
+    Galaxies::new(lines, expansion).sum_of_distances()
 }
 
-pub fn p1(lines: &Vec<String>) -> usize {
-    let universe = parse_lines(lines);
-    let galaxies = get_all_galaxies(&universe, 2);
-    get_all_pairwise_distances(&galaxies).values().sum()
+pub fn p1(lines: &[String]) -> u128 {
+    solve(lines, Expansion::uniform(2).unwrap())
 }
 
-pub fn p2(lines: &Vec<String>) -> usize {
-    let universe = parse_lines(lines);
-    let galaxies = get_all_galaxies(&universe, 1000000);
-    get_all_pairwise_distances(&galaxies).values().sum()
+pub fn p2(lines: &[String]) -> u128 {
+    solve(lines, Expansion::uniform(1000000).unwrap())
 }
@@ -1,112 +1,610 @@
-use memoize::memoize;
+use num_bigint::BigUint;
+use rand::Rng;
+use std::collections::HashMap;
 
-fn parse_lines(lines: &Vec<String>) -> (Vec<String>, Vec<String>) {
-    let mut springs: Vec<String> = Vec::new();
-    let mut records: Vec<String> = Vec::new();
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Row {
+    // '#' damaged, '.' operational, '?' unknown
+    pub springs: String,
+    // sizes of the runs of damaged springs, in order
+    pub groups: Vec<usize>,
+}
 
-    lines.iter().for_each(|line| {
-        let mut split = line.split_whitespace();
-        springs.push(split.next().unwrap().to_string());
-        records.push(split.next().unwrap().to_string());
-    });
+fn parse_lines(lines: &[String]) -> Vec<Row> {
+    lines
+        .iter()
+        .map(|line| {
+            let mut split = line.split_whitespace();
+            let springs = split.next().unwrap().to_string();
+            // a row without a record has no groups, but every listed group has to be a number
+            let groups = match split.next() {
+                None => Vec::new(),
+                Some(record) => record
+                    .split(',')
+                    .map(|s| {
+                        s.parse::<usize>().unwrap_or_else(|_| {
+                            panic!("{} of length {} is not a number", s, s.len())
+                        })
+                    })
+                    .collect(),
+            };
+            Row { springs, groups }
+        })
+        .collect()
+}
 
-    (springs, records)
+// counts arrangements one spring at a time over states (groups finished, length of the current
+// run of '#'); the two layers are reused between rows, so counting allocates nothing once they
+// are big enough
+#[derive(Clone, Debug, Default)]
+pub struct ArrangementCounter {
+    cur: Vec<usize>,
+    next: Vec<usize>,
+    // (position, group index, run) states reached with a non-zero count, over every row so far
+    pub states: usize,
 }
 
-#[memoize]
-fn find_num_arrangements(spring: String, records: String) -> usize {
-    let parsed_records: Vec<usize> = if records.is_empty() {
-        Vec::new()
-    } else {
-        records
-            .split(',')
-            .map(|s| {
-                s.parse::<usize>()
-                    .unwrap_or_else(|_| panic!("{} of length {} is not a number", s, s.len()))
-            })
-            .collect::<Vec<usize>>()
-    };
-    let parsed_spring: Vec<char> = spring.chars().collect::<Vec<char>>();
+impl ArrangementCounter {
+    pub fn count(&mut self, springs: &[u8], groups: &[usize]) -> usize {
+        let width = groups.iter().copied().max().unwrap_or(0) + 1;
+        let size = (groups.len() + 1) * width;
+        let idx = |g: usize, run: usize| g * width + run;
 
-    if parsed_spring.is_empty() {
-        return parsed_records.is_empty() as usize;
-    } else if parsed_records.is_empty() {
-        return !parsed_spring.iter().any(|c| *c == '#') as usize;
-    } else if parsed_spring.len() < parsed_records.iter().sum::<usize>() + parsed_records.len() - 1
-    {
-        return 0;
+        self.cur.clear();
+        self.cur.resize(size, 0);
+        self.next.clear();
+        self.next.resize(size, 0);
+        self.cur[idx(0, 0)] = 1;
+
+        // a trailing '.' closes the last run, so every row is checked the same way
+        for &spring in springs.iter().chain(std::iter::once(&b'.')) {
+            self.next.fill(0);
+
+            for g in 0..=groups.len() {
+                for run in 0..width {
+                    let ways = self.cur[idx(g, run)];
+                    if ways == 0 {
+                        continue;
+                    }
+                    self.states += 1;
+
+                    if spring != b'#' {
+                        if run == 0 {
+                            self.next[idx(g, 0)] += ways;
+                        } else if run == groups[g] {
+                            self.next[idx(g + 1, 0)] += ways;
+                        }
+                    }
+                    if spring != b'.' && g < groups.len() && run < groups[g] {
+                        self.next[idx(g, run + 1)] += ways;
+                    }
+                }
+            }
+
+            std::mem::swap(&mut self.cur, &mut self.next);
+        }
+
+        self.cur[idx(groups.len(), 0)]
     }
+}
 
-    if parsed_spring[0] == '.' {
-        return find_num_arrangements(spring[1..].to_string(), records);
-    } else if parsed_spring[0] == '#' {
-        let first_record = parsed_records[0];
-        for i in 0..first_record {
-            if parsed_spring[i] == '.' {
-                return 0;
+// ways[i * (groups + 1) + g] counts the arrangements of springs[i..] holding groups[g..],
+// starting right after a '.' or at the start of the row
+#[derive(Clone, Debug)]
+pub struct CountTable {
+    springs: Vec<u8>,
+    groups: Vec<usize>,
+    ways: Vec<usize>,
+}
+
+impl CountTable {
+    pub fn new(row: &Row) -> CountTable {
+        let springs = row.springs.as_bytes().to_vec();
+        let groups = row.groups.clone();
+        let (n, width) = (springs.len(), groups.len() + 1);
+        let mut ways = vec![0; (n + 1) * width];
+        ways[n * width + groups.len()] = 1;
+
+        for i in (0..n).rev() {
+            for g in 0..width {
+                let mut total = 0;
+                if springs[i] != b'#' {
+                    total += ways[(i + 1) * width + g];
+                }
+                if let Some(after) = Self::fits_group(&springs, &groups, i, g) {
+                    total += ways[after * width + g + 1];
+                }
+                ways[i * width + g] = total;
             }
         }
-        if first_record < parsed_spring.len() && parsed_spring[first_record] == '#' {
-            return 0;
+
+        CountTable {
+            springs,
+            groups,
+            ways,
         }
+    }
 
-        let s_nxt: String = if first_record < parsed_spring.len() {
-            spring[first_record + 1..].to_string()
-        } else {
-            String::new()
-        };
-        return find_num_arrangements(
-            s_nxt,
-            parsed_records[1..]
-                .iter()
-                .map(|x| x.to_string())
-                .collect::<Vec<String>>()
-                .join(","),
+    // where the next group can start if group g is placed at i, followed by a '.' unless the
+    // row ends there
+    fn fits_group(springs: &[u8], groups: &[usize], i: usize, g: usize) -> Option<usize> {
+        let end = i + *groups.get(g)?;
+        let fits = end <= springs.len()
+            && !springs[i..end].contains(&b'.')
+            && springs.get(end) != Some(&b'#');
+        fits.then_some((end + 1).min(springs.len()))
+    }
+
+    fn ways(&self, i: usize, g: usize) -> usize {
+        self.ways[i * (self.groups.len() + 1) + g]
+    }
+
+    pub fn total(&self) -> usize {
+        self.ways(0, 0)
+    }
+
+    // arrangements sort with '#' before '.', as the bytes do; None once k runs past the end
+    pub fn kth(&self, mut k: usize) -> Option<String> {
+        if k >= self.total() {
+            return None;
+        }
+
+        let mut out = String::with_capacity(self.springs.len());
+        let (mut i, mut g) = (0, 0);
+        while i < self.springs.len() {
+            if let Some(after) = Self::fits_group(&self.springs, &self.groups, i, g) {
+                let with_group = self.ways(after, g + 1);
+                if k < with_group {
+                    out.extend(std::iter::repeat_n('#', self.groups[g]));
+                    if after > i + self.groups[g] {
+                        out.push('.');
+                    }
+                    i = after;
+                    g += 1;
+                    continue;
+                }
+                k -= with_group;
+            }
+
+            out.push('.');
+            i += 1;
+        }
+
+        Some(out)
+    }
+
+    pub fn arrangements(&self) -> impl Iterator<Item = String> + '_ {
+        (0..self.total()).map(|k| self.kth(k).unwrap())
+    }
+
+    // every arrangement is equally likely; None if the row has none
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<String> {
+        match self.total() {
+            0 => None,
+            total => self.kth(rng.gen_range(0..total)),
+        }
+    }
+}
+
+// a fully specified row, e.g. one sampled above, against the record it should satisfy
+pub fn check(row: &Row, arrangement: &str) -> Result<(), String> {
+    if arrangement.len() != row.springs.len() {
+        return Err(format!(
+            "{} has {} springs, the row has {}",
+            arrangement,
+            arrangement.len(),
+            row.springs.len()
+        ));
+    }
+
+    for (pos, (want, got)) in row.springs.chars().zip(arrangement.chars()).enumerate() {
+        if got != '#' && got != '.' {
+            return Err(format!("spring {} of {} is {}", pos, arrangement, got));
+        }
+        if want != '?' && want != got {
+            return Err(format!(
+                "spring {} of {} is {} but the row says {}",
+                pos, arrangement, got, want
+            ));
+        }
+    }
+
+    let runs: Vec<usize> = arrangement
+        .split('.')
+        .filter(|run| !run.is_empty())
+        .map(|run| run.len())
+        .collect();
+    if runs != row.groups {
+        return Err(format!(
+            "{} has runs {:?}, the record is {:?}",
+            arrangement, runs, row.groups
+        ));
+    }
+
+    Ok(())
+}
+
+// `factor` copies of the springs joined by `separator`, and as many copies of the record
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Unfold {
+    pub factor: u64,
+    pub separator: char,
+}
+
+impl Default for Unfold {
+    fn default() -> Unfold {
+        Unfold {
+            factor: 5,
+            separator: '?',
+        }
+    }
+}
+
+impl Unfold {
+    pub fn validate(&self) -> Result<(), String> {
+        match self.separator {
+            '#' | '.' | '?' => Ok(()),
+            other => Err(format!("{:?} is not a spring, use '#', '.' or '?'", other)),
+        }
+    }
+}
+
+// the unfolded row spelled out, only sensible for small factors
+pub fn unfold_row(row: &Row, unfold: Unfold) -> Row {
+    let copies = vec![row.springs.as_str(); unfold.factor as usize];
+
+    Row {
+        springs: copies.join(&unfold.separator.to_string()),
+        groups: row.groups.repeat(unfold.factor as usize),
+    }
+}
+
+// between copies: (groups finished minus the groups of the copies finished so far, current run)
+type Boundary = (i64, usize);
+
+// feeds one copy of the springs, then the separator unless this is the last copy, to every
+// state; the offsets of the result are relative to the next copy
+fn step_copy(
+    row: &Row,
+    states: &HashMap<Boundary, BigUint>,
+    separator: Option<u8>,
+) -> HashMap<Boundary, BigUint> {
+    let n = row.groups.len() as i64;
+    let size = |g: i64| row.groups[g.rem_euclid(n) as usize];
+    let mut cur = states.clone();
+
+    let closing: &[u8] = if separator.is_none() { b"." } else { b"" };
+    for &spring in row
+        .springs
+        .as_bytes()
+        .iter()
+        .chain(separator.iter())
+        .chain(closing)
+    {
+        let mut next: HashMap<Boundary, BigUint> = HashMap::new();
+
+        for (&(g, run), ways) in cur.iter() {
+            if spring != b'#' {
+                if run == 0 {
+                    *next.entry((g, 0)).or_default() += ways;
+                } else if run == size(g) {
+                    *next.entry((g + 1, 0)).or_default() += ways;
+                }
+            }
+            if spring != b'.' && run < size(g) {
+                *next.entry((g, run + 1)).or_default() += ways;
+            }
+        }
+
+        cur = next;
+    }
+
+    cur.into_iter()
+        .map(|((g, run), ways)| ((g - n, run), ways))
+        .collect()
+}
+
+// the most groups one copy and its separator can finish: a run carried in can end on the
+// first spring, every other group needs a '#' and then a '.'
+fn most_per_copy(row: &Row) -> i64 {
+    1 + row.springs.len() as i64 / 2
+}
+
+// the boundary states reachable from the start and the one-copy transfer between them, or
+// None if the offsets are unbounded. A copy only sees the offset modulo the number of groups,
+// so with n * (longest group + 1) of those classes, an offset reached by a path that repeats
+// no class is at most that many copies' worth of change away from 0; anything further out
+// means some cycle of copies keeps moving the offset and the states never close up
+fn transfer_matrix(row: &Row, separator: u8) -> Option<(Vec<Boundary>, Vec<Vec<BigUint>>)> {
+    let n = row.groups.len() as i64;
+    let classes = n * (*row.groups.iter().max().unwrap() as i64 + 1);
+    let bound = classes * n.max(most_per_copy(row));
+
+    let mut states: Vec<Boundary> = vec![(0, 0)];
+    let mut index: HashMap<Boundary, usize> = HashMap::from([((0, 0), 0)]);
+    let mut edges: Vec<Vec<(usize, BigUint)>> = Vec::new();
+
+    while edges.len() < states.len() {
+        let from = states[edges.len()];
+        let after = step_copy(
+            row,
+            &HashMap::from([(from, BigUint::from(1_u32))]),
+            Some(separator),
         );
+
+        let mut out = Vec::new();
+        for (to, ways) in after {
+            if to.0.abs() > bound {
+                return None;
+            }
+            let idx = *index.entry(to).or_insert_with(|| {
+                states.push(to);
+                states.len() - 1
+            });
+            out.push((idx, ways));
+        }
+        edges.push(out);
+    }
+
+    let mut matrix = vec![vec![BigUint::ZERO; states.len()]; states.len()];
+    for (from, out) in edges.into_iter().enumerate() {
+        for (to, ways) in out {
+            matrix[from][to] = ways;
+        }
+    }
+
+    Some((states, matrix))
+}
+
+fn mul_vec(v: &[BigUint], m: &[Vec<BigUint>]) -> Vec<BigUint> {
+    (0..m.len())
+        .map(|j| v.iter().zip(m.iter()).map(|(x, row)| x * &row[j]).sum())
+        .collect()
+}
+
+fn mul_mat(a: &[Vec<BigUint>], b: &[Vec<BigUint>]) -> Vec<Vec<BigUint>> {
+    a.iter().map(|row| mul_vec(row, b)).collect()
+}
+
+// rows whose copies can fit a varying number of groups, such as `??? 1`, are counted one
+// copy at a time; that keeps a state per offset, each holding a count that grows with the
+// factor, so it slows down roughly with the cube of the factor and is refused past this
+pub const MAX_STEPWISE_FACTOR: u64 = 1000;
+
+// the largest factor count_unfolded accepts for this row, None if there is no limit
+pub fn max_unfold_factor(row: &Row, separator: char) -> Option<u64> {
+    if row.groups.is_empty() || transfer_matrix(row, separator as u8).is_some() {
+        None
+    } else {
+        Some(MAX_STEPWISE_FACTOR)
+    }
+}
+
+// number of arrangements of the unfolded row without spelling it out: every copy but the
+// last goes through the transfer matrix, raised to the power by squaring, and the last copy
+// has to end on exactly the last group. This handles any factor as long as the copies finish
+// a bounded number of groups more or fewer than the record holds, which is true of puzzle
+// rows as their '#'s pin the groups down. Other rows are limited to MAX_STEPWISE_FACTOR
+// copies (see max_unfold_factor) and get an Err beyond it
+pub fn count_unfolded(row: &Row, unfold: Unfold) -> Result<BigUint, String> {
+    unfold.validate()?;
+    let no_damage = |springs: &str| !springs.contains('#');
+    if unfold.factor == 0 {
+        return Ok(BigUint::from(1_u32));
     }
+    if row.groups.is_empty() {
+        let fits = no_damage(&row.springs) && (unfold.factor == 1 || unfold.separator != '#');
+        return Ok(BigUint::from(fits as u32));
+    }
+
+    let separator = unfold.separator as u8;
+    let finish = |from: Boundary| -> BigUint {
+        let last = step_copy(row, &HashMap::from([(from, BigUint::from(1_u32))]), None);
+        last.get(&(0, 0)).cloned().unwrap_or_default()
+    };
 
-    // ? - split into two cases
-    let (mut s1, mut s2): (String, String) = (String::from('#'), String::from('.'));
-    s1.push_str(&spring[1..]);
-    s2.push_str(&spring[1..]);
+    let Some((states, matrix)) = transfer_matrix(row, separator) else {
+        if unfold.factor > MAX_STEPWISE_FACTOR {
+            return Err(format!(
+                "{} {:?} fits a varying number of groups per copy, so it can only be unfolded up to {} times, not {}",
+                row.springs, row.groups, MAX_STEPWISE_FACTOR, unfold.factor
+            ));
+        }
+        return Ok(count_unfolded_stepwise(row, unfold));
+    };
 
-    return find_num_arrangements(s1, records.clone()) + find_num_arrangements(s2, records.clone());
+    let mut v: Vec<BigUint> = vec![BigUint::ZERO; states.len()];
+    v[0] = BigUint::from(1_u32);
+    let mut power = matrix;
+    let mut e = unfold.factor - 1;
+    while e > 0 {
+        if e & 1 == 1 {
+            v = mul_vec(&v, &power);
+        }
+        e >>= 1;
+        if e > 0 {
+            power = mul_mat(&power, &power);
+        }
+    }
+
+    Ok(states
+        .iter()
+        .zip(v.iter())
+        .filter(|(_, ways)| **ways != BigUint::ZERO)
+        .map(|(&from, ways)| ways * finish(from))
+        .sum())
 }
 
-fn dup_spring_and_record(spring: &String, record: &String) -> (String, String) {
-    let mut new_spring = spring.clone();
-    let mut new_record = record.clone();
+// offsets that can no longer get back to 0 are dropped as we go
+fn count_unfolded_stepwise(row: &Row, unfold: Unfold) -> BigUint {
+    let n = row.groups.len() as i64;
+    let most_per_copy = most_per_copy(row);
 
-    for _ in 0..4 {
-        new_spring.push('?');
-        new_spring.push_str(&mut spring.clone());
+    let mut states: HashMap<Boundary, BigUint> = HashMap::from([((0, 0), BigUint::from(1_u32))]);
+    for copy in 1..=unfold.factor {
+        let separator = (copy < unfold.factor).then_some(unfold.separator as u8);
+        let remaining = (unfold.factor - copy) as i64;
 
-        new_record.push(',');
-        new_record.push_str(&mut record.clone());
+        states = step_copy(row, &states, separator);
+        states.retain(|&(g, _), _| g <= remaining * n && g + remaining * (most_per_copy - n) >= 0);
     }
 
-    (new_spring, new_record)
+    states.remove(&(0, 0)).unwrap_or_default()
 }
 
-pub fn p1(lines: &Vec<String>) -> usize {
-    let (springs, records) = parse_lines(lines);
-    springs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Totals {
+    pub arrangements: usize,
+    pub states: usize,
+}
+
+pub fn count_all(rows: &[Row]) -> Totals {
+    let mut counter = ArrangementCounter::default();
+    let arrangements = rows
         .iter()
-        .zip(records.iter())
-        .fold(0, |acc, (spring, records)| {
-            let cur_arrangements = find_num_arrangements(spring.clone(), records.clone());
-            acc + cur_arrangements
-        })
+        .map(|row| counter.count(row.springs.as_bytes(), &row.groups))
+        .sum();
+
+    Totals {
+        arrangements,
+        states: counter.states,
+    }
+}
+
+pub fn p1(lines: &[String]) -> usize {
+    count_all(&parse_lines(lines)).arrangements
 }
 
-pub fn p2(lines: &Vec<String>) -> usize {
-    let (springs, records) = parse_lines(lines);
-    springs
+pub fn p2(lines: &[String]) -> BigUint {
+    parse_lines(lines)
         .iter()
-        .zip(records.iter())
-        .fold(0, |acc, (spring, records)| {
-            let (extended_spring, extended_records) = dup_spring_and_record(spring, records);
-            let cur_arrangements = find_num_arrangements(extended_spring, extended_records);
-            acc + cur_arrangements
-        })
+        .map(|row| count_unfolded(row, Unfold::default()).unwrap_or_else(|e| panic!("{}", e)))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(springs: &str, groups: &[usize]) -> Row {
+        Row {
+            springs: springs.to_string(),
+            groups: groups.to_vec(),
+        }
+    }
+
+    // the unfolded row spelled out and counted by the plain DP
+    fn expected(row: &Row, unfold: Unfold) -> BigUint {
+        let unfolded = unfold_row(row, unfold);
+        let count =
+            ArrangementCounter::default().count(unfolded.springs.as_bytes(), &unfolded.groups);
+        BigUint::from(count)
+    }
+
+    fn check_small_factors(row: &Row) {
+        for separator in ['?', '.', '#'] {
+            for factor in 0..6 {
+                let unfold = Unfold { factor, separator };
+                assert_eq!(
+                    count_unfolded(row, unfold),
+                    Ok(expected(row, unfold)),
+                    "{:?} {:?}",
+                    row,
+                    unfold
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transfer_matrix_matches_unfolded_rows() {
+        let rows = [
+            row("???.###", &[1, 1, 3]),
+            row(".??..??...?##.", &[1, 1, 3]),
+            row("?###????????", &[3, 2, 1]),
+            row("#.#", &[1, 1]),
+            row("?#", &[2]),
+        ];
+
+        for row in rows.iter() {
+            for separator in [b'?', b'.', b'#'] {
+                assert!(transfer_matrix(row, separator).is_some(), "{:?}", row);
+            }
+            check_small_factors(row);
+        }
+    }
+
+    #[test]
+    fn stepwise_matches_unfolded_rows() {
+        let rows = [row("???", &[1]), row("?.?", &[1, 1]), row("??#?", &[1])];
+
+        for row in rows.iter() {
+            assert!(transfer_matrix(row, b'?').is_none(), "{:?}", row);
+            check_small_factors(row);
+
+            for separator in ['?', '.', '#'] {
+                for factor in 1..6 {
+                    let unfold = Unfold { factor, separator };
+                    assert_eq!(count_unfolded_stepwise(row, unfold), expected(row, unfold));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rows_without_groups() {
+        check_small_factors(&row("..?", &[]));
+        check_small_factors(&row(".#", &[]));
+    }
+
+    #[test]
+    fn rejects_other_separators() {
+        let unfold = Unfold {
+            factor: 2,
+            separator: 'é',
+        };
+        assert!(count_unfolded(&row("???", &[1]), unfold).is_err());
+    }
+
+    #[test]
+    fn unfold_factor_limit() {
+        let bounded = [
+            row("?###????????", &[3, 2, 1]),
+            row("????.######..#####.", &[1, 6, 5]),
+            row("?##?", &(3..30).collect::<Vec<usize>>()),
+        ];
+        let unbounded = [
+            row("???", &[1]),
+            row("????????", &[1, 1]),
+            row("?.?", &[1, 1]),
+            row("??#", &[5, 6, 7]),
+        ];
+        let huge = Unfold {
+            factor: u64::MAX,
+            separator: '?',
+        };
+        let past_limit = Unfold {
+            factor: MAX_STEPWISE_FACTOR + 1,
+            separator: '?',
+        };
+
+        for row in bounded.iter() {
+            assert_eq!(max_unfold_factor(row, '?'), None, "{:?}", row);
+            let unfold = Unfold {
+                factor: 10_000,
+                separator: '?',
+            };
+            assert!(count_unfolded(row, unfold).is_ok(), "{:?}", row);
+        }
+        for row in unbounded.iter() {
+            assert_eq!(
+                max_unfold_factor(row, '?'),
+                Some(MAX_STEPWISE_FACTOR),
+                "{:?}",
+                row
+            );
+            assert!(count_unfolded(row, past_limit).is_err(), "{:?}", row);
+            assert!(count_unfolded(row, huge).is_err(), "{:?}", row);
+        }
+    }
 }
@@ -1,28 +1,55 @@
-fn parse_engine(all_lines: &Vec<String>) -> Vec<Vec<(i32, i32, String)>> {
-    let mut v: Vec<Vec<(i32, i32, String)>> = Vec::new();
-    let length_line = all_lines.get(0).unwrap().len() as i32;
+use std::{collections::HashMap, ops::RangeInclusive};
 
-    for line in all_lines {
-        let mut cur_num = String::new();
-        let mut vv: Vec<(i32, i32, String)> = Vec::new();
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Token {
+    Number { value: i32, span: (usize, usize) },
+    Symbol { ch: char, pos: usize },
+}
 
-        for (ch_idx, ch) in line.chars().enumerate() {
-            let is_digit = ch.is_digit(10);
-            let ch_num = ch_idx as i32;
+// whether a '-' directly before a digit is the number's sign or a symbol of its own
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Minus {
+    Symbol,
+    Sign,
+}
 
-            if is_digit {
-                cur_num.push(ch);
-            } else if ch != '.' {
-                vv.push((ch_num, ch_num, ch.to_string()))
-            }
+pub fn parse_engine(all_lines: &[String], minus: Minus) -> Vec<Vec<Token>> {
+    let mut v: Vec<Vec<Token>> = Vec::new();
+
+    for line in all_lines {
+        let chars: Vec<char> = line.chars().collect();
+        let mut vv: Vec<Token> = Vec::new();
+        let mut pos = 0;
+
+        while pos < chars.len() {
+            let ch = chars[pos];
+            let is_sign = minus == Minus::Sign
+                && ch == '-'
+                && chars.get(pos + 1).is_some_and(|c| c.is_ascii_digit());
 
-            if !is_digit || ch_num == length_line - 1 {
-                if !cur_num.is_empty() {
-                    vv.push((ch_num - cur_num.len() as i32, ch_num - 1, cur_num.clone()))
+            if ch.is_ascii_digit() || is_sign {
+                let start = pos;
+                pos += 1;
+                while pos < chars.len() && chars[pos].is_ascii_digit() {
+                    pos += 1;
                 }
 
-                cur_num.clear();
+                let value = chars[start..pos]
+                    .iter()
+                    .collect::<String>()
+                    .parse()
+                    .unwrap();
+                vv.push(Token::Number {
+                    value,
+                    span: (start, pos - 1),
+                });
+                continue;
             }
+
+            if ch != '.' {
+                vv.push(Token::Symbol { ch, pos });
+            }
+            pos += 1;
         }
 
         v.push(vv);
@@ -31,109 +58,285 @@ fn parse_engine(all_lines: &Vec<String>) -> Vec<Vec<(i32, i32, String)>> {
     v
 }
 
-fn is_digit(s: &String) -> bool {
-    return s.chars().nth(0).unwrap_or('f').is_digit(10);
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Part {
+    pub value: i32,
+    pub row: i32,
+    pub start: i32,
+    pub end: i32,
 }
 
-pub fn p1(all_lines: &Vec<String>) -> i32 {
-    let mut tot_sum = 0;
-    let empty_vec: Vec<(i32, i32, String)> = Vec::new();
-
-    let v = parse_engine(all_lines);
-    let n = v.len();
-
-    for i in 0..n {
-        let v_prev = match i {
-            0 => &empty_vec,
-            _ => v.get(i - 1).unwrap(),
-        };
-        let v_nxt = match i {
-            last_elem if last_elem == n - 1 => &empty_vec,
-            _ => v.get(i + 1).unwrap(),
-        };
-        let v_cur = v.get(i).unwrap();
-
-        for (start, end, s) in v_cur.iter() {
-            if is_digit(s) {
-                let num: i32 = s.parse().unwrap();
-                for (start2, end2, s2) in v_prev.iter().chain(v_cur.iter()).chain(v_nxt.iter()) {
-                    if !is_digit(s2) && s2 != "." {
-                        let mut break_out = false;
-                        for incr in -1..2 {
-                            for pts in vec![start, end] {
-                                if (pts + incr) >= *start2 && (pts + incr) <= *end2 {
-                                    tot_sum += num;
-                                    break_out = true;
-                                    break;
-                                }
-                            }
-                            if break_out {
-                                break;
-                            }
-                        }
-                        if break_out {
-                            break;
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub ch: char,
+    pub row: i32,
+    pub col: i32,
+}
+
+#[derive(Debug)]
+pub struct Schematic {
+    pub parts: Vec<Part>,
+    pub symbols: Vec<Symbol>,
+    part_cells: HashMap<(i32, i32), usize>,
+    symbol_cells: HashMap<(i32, i32), usize>,
+}
+
+impl Schematic {
+    pub fn new(all_lines: &[String]) -> Schematic {
+        Schematic::from_tokens(&parse_engine(all_lines, Minus::Symbol))
+    }
+
+    pub fn from_tokens(rows: &[Vec<Token>]) -> Schematic {
+        let mut parts: Vec<Part> = Vec::new();
+        let mut symbols: Vec<Symbol> = Vec::new();
+        let mut part_cells: HashMap<(i32, i32), usize> = HashMap::new();
+        let mut symbol_cells: HashMap<(i32, i32), usize> = HashMap::new();
+
+        for (row, tokens) in rows.iter().enumerate() {
+            let row = row as i32;
+            for token in tokens {
+                match *token {
+                    Token::Number { value, span } => {
+                        let (start, end) = (span.0 as i32, span.1 as i32);
+                        for col in start..=end {
+                            part_cells.insert((row, col), parts.len());
                         }
+                        parts.push(Part {
+                            value,
+                            row,
+                            start,
+                            end,
+                        });
+                    }
+                    Token::Symbol { ch, pos } => {
+                        let col = pos as i32;
+                        symbol_cells.insert((row, col), symbols.len());
+                        symbols.push(Symbol { ch, row, col });
                     }
                 }
             }
         }
+
+        Schematic {
+            parts,
+            symbols,
+            part_cells,
+            symbol_cells,
+        }
     }
 
-    tot_sum
-}
+    // indices of the parts whose digits touch the symbol, each part reported once
+    pub fn parts_touching(&self, symbol_idx: usize) -> Vec<usize> {
+        let symbol = &self.symbols[symbol_idx];
+        let mut touching: Vec<usize> = Vec::new();
 
-pub fn p2(all_lines: &Vec<String>) -> i32 {
-    let mut tot_sum = 0;
-    let empty_vec: Vec<(i32, i32, String)> = Vec::new();
-
-    let v = parse_engine(all_lines);
-    let n = v.len();
-
-    for i in 0..n {
-        let v_prev = match i {
-            0 => &empty_vec,
-            _ => v.get(i - 1).unwrap(),
-        };
-        let v_nxt = match i {
-            last_elem if last_elem == n - 1 => &empty_vec,
-            _ => v.get(i + 1).unwrap(),
-        };
-        let v_cur = v.get(i).unwrap();
-
-        for (start, end, s) in v_cur.iter() {
-            let mut digit_cnt = 0;
-            let mut list_digits: Vec<i32> = Vec::new();
-
-            if s == "*" {
-                for (start2, end2, s2) in v_prev.iter().chain(v_cur.iter()).chain(v_nxt.iter()) {
-                    let mut break_out = false;
-
-                    if is_digit(s2) {
-                        let num: i32 = s2.parse().unwrap();
-
-                        for incr in -1..2 {
-                            for pts in vec![start, end] {
-                                if (pts + incr) >= *start2 && (pts + incr) <= *end2 {
-                                    list_digits.push(num);
-                                    digit_cnt += 1;
-                                    break_out = true;
-                                    break;
-                                }
-                            }
-                            if break_out || digit_cnt > 2 {
-                                break;
-                            }
-                        }
+        for row in symbol.row - 1..=symbol.row + 1 {
+            for col in symbol.col - 1..=symbol.col + 1 {
+                if let Some(&part_idx) = self.part_cells.get(&(row, col)) {
+                    if !touching.contains(&part_idx) {
+                        touching.push(part_idx);
                     }
                 }
+            }
+        }
+
+        touching
+    }
+
+    // indices of the symbols on the border of the part's bounding box
+    pub fn symbols_touching(&self, part_idx: usize) -> Vec<usize> {
+        let part = &self.parts[part_idx];
+        let mut touching: Vec<usize> = Vec::new();
 
-                if digit_cnt == 2 {
-                    tot_sum += list_digits.get(0).unwrap() * list_digits.get(1).unwrap();
+        for row in part.row - 1..=part.row + 1 {
+            for col in part.start - 1..=part.end + 1 {
+                if let Some(&symbol_idx) = self.symbol_cells.get(&(row, col)) {
+                    touching.push(symbol_idx);
                 }
             }
         }
+
+        touching
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Combine {
+    Product,
+    Sum,
+    Max,
+}
+
+impl Combine {
+    pub fn apply(&self, values: &[i32]) -> i32 {
+        match self {
+            Combine::Product => values.iter().product(),
+            Combine::Sum => values.iter().sum(),
+            Combine::Max => *values.iter().max().unwrap_or(&0),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GearRule {
+    pub symbols: Vec<char>,
+    pub neighbors: RangeInclusive<usize>,
+    pub combine: Combine,
+}
+
+impl Default for GearRule {
+    // a '*' touching exactly two parts, whose ratio is their product
+    fn default() -> GearRule {
+        GearRule::new("*", 2..=2, Combine::Product)
+    }
+}
+
+impl GearRule {
+    pub fn new(symbols: &str, neighbors: RangeInclusive<usize>, combine: Combine) -> GearRule {
+        GearRule {
+            symbols: symbols.chars().collect(),
+            neighbors,
+            combine,
+        }
+    }
+
+    // (symbol index, combined part values) for every symbol satisfying the rule
+    pub fn gears(&self, schematic: &Schematic) -> Vec<(usize, i32)> {
+        let mut gears: Vec<(usize, i32)> = Vec::new();
+
+        for (symbol_idx, symbol) in schematic.symbols.iter().enumerate() {
+            if !self.symbols.contains(&symbol.ch) {
+                continue;
+            }
+
+            let values: Vec<i32> = schematic
+                .parts_touching(symbol_idx)
+                .iter()
+                .map(|&part_idx| schematic.parts[part_idx].value)
+                .collect();
+
+            if self.neighbors.contains(&values.len()) {
+                gears.push((symbol_idx, self.combine.apply(&values)));
+            }
+        }
+
+        gears
+    }
+
+    pub fn total(&self, schematic: &Schematic) -> i32 {
+        self.gears(schematic).iter().map(|&(_, value)| value).sum()
     }
+}
+
+pub fn p1(all_lines: &[String]) -> i32 {
+    let schematic = Schematic::new(all_lines);
 
-    tot_sum
+    (0..schematic.parts.len())
+        .filter(|&part_idx| !schematic.symbols_touching(part_idx).is_empty())
+        .map(|part_idx| schematic.parts[part_idx].value)
+        .sum()
+}
+
+pub fn p2(all_lines: &[String]) -> i32 {
+    let schematic = Schematic::new(all_lines);
+    GearRule::default().total(&schematic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn number_at_last_column() {
+        let rows = parse_engine(&to_lines(&["..*.123"]), Minus::Symbol);
+        assert_eq!(
+            rows[0],
+            vec![
+                Token::Symbol { ch: '*', pos: 2 },
+                Token::Number {
+                    value: 123,
+                    span: (4, 6)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers_touching_every_edge() {
+        let lines = to_lines(&["12...", "..#..", "5...7", "...$.", "..9.."]);
+        let schematic = Schematic::new(&lines);
+        let values: Vec<i32> = schematic.parts.iter().map(|p| p.value).collect();
+
+        assert_eq!(values, vec![12, 5, 7, 9]);
+        assert_eq!(p1(&lines), 12 + 7 + 9);
+    }
+
+    #[test]
+    fn ragged_rows() {
+        let lines = to_lines(&["...", "....*", "......42"]);
+        let rows = parse_engine(&lines, Minus::Symbol);
+
+        assert_eq!(
+            rows[2],
+            vec![Token::Number {
+                value: 42,
+                span: (6, 7)
+            }]
+        );
+        assert_eq!(p1(&lines), 0);
+        assert_eq!(p1(&to_lines(&["...", "....*", ".....42"])), 42);
+    }
+
+    #[test]
+    fn wide_number_touches_symbol_past_its_endpoints() {
+        let lines = to_lines(&["123456", "......", "..#..."]);
+        assert_eq!(p1(&lines), 0);
+
+        let lines = to_lines(&["123456", "...#.."]);
+        assert_eq!(p1(&lines), 123456);
+    }
+
+    #[test]
+    fn minus_as_sign_or_symbol() {
+        let lines = to_lines(&["5.-12"]);
+
+        assert_eq!(
+            parse_engine(&lines, Minus::Sign)[0],
+            vec![
+                Token::Number {
+                    value: 5,
+                    span: (0, 0)
+                },
+                Token::Number {
+                    value: -12,
+                    span: (2, 4)
+                },
+            ]
+        );
+        assert_eq!(
+            parse_engine(&lines, Minus::Symbol)[0],
+            vec![
+                Token::Number {
+                    value: 5,
+                    span: (0, 0)
+                },
+                Token::Symbol { ch: '-', pos: 2 },
+                Token::Number {
+                    value: 12,
+                    span: (3, 4)
+                },
+            ]
+        );
+        assert_eq!(p1(&lines), 12);
+    }
+
+    #[test]
+    fn trailing_minus_is_a_symbol() {
+        let rows = parse_engine(&to_lines(&["7-"]), Minus::Sign);
+        assert_eq!(rows[0][1], Token::Symbol { ch: '-', pos: 1 });
+    }
 }
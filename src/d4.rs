@@ -1,9 +1,11 @@
-use std::collections::HashSet;
+use std::{
+    collections::{HashSet, VecDeque},
+    io::BufRead,
+};
 
 fn split_line(line: &str) -> &str {
     let mut split = line.split(":");
-    let snd = split.nth(1).unwrap().trim();
-    return snd;
+    split.nth(1).unwrap().trim()
 }
 
 fn parse_line(line: &str) -> HashSet<i32> {
@@ -14,50 +16,161 @@ fn parse_line(line: &str) -> HashSet<i32> {
         .collect::<HashSet<i32>>()
 }
 
-fn parse_lines(lines: &Vec<String>) -> (Vec<HashSet<i32>>, Vec<HashSet<i32>>) {
+fn parse_card(line: &str) -> (HashSet<i32>, HashSet<i32>) {
+    let mut split = line.split("|");
+    let win = parse_line(split_line(split.next().unwrap()));
+    let have = parse_line(split.next().unwrap());
+    (win, have)
+}
+
+pub fn parse_lines(lines: &[String]) -> (Vec<HashSet<i32>>, Vec<HashSet<i32>>) {
     let mut v1: Vec<HashSet<i32>> = Vec::new();
     let mut v2: Vec<HashSet<i32>> = Vec::new();
 
     for line in lines {
-        let mut split = line.split("|");
-        v1.push(parse_line(split_line(split.next().unwrap())));
-        v2.push(parse_line(split.next().unwrap()));
+        let (win, have) = parse_card(line);
+        v1.push(win);
+        v2.push(have);
     }
 
-    return (v1, v2);
+    (v1, v2)
 }
 
-pub fn p1(lines: &Vec<String>) -> usize {
-    let (winning, having) = parse_lines(lines);
-    let mut tot_pts = 0;
+pub fn count_matches(win: &HashSet<i32>, have: &HashSet<i32>) -> usize {
+    win.intersection(have).count()
+}
+
+// 1 point for the first match, doubled for every match after it
+pub fn doubling_points(matching: usize) -> usize {
+    if matching > 0 {
+        2_usize.pow((matching - 1) as u32)
+    } else {
+        0
+    }
+}
+
+// a copy of each of the next `matching` cards
+pub fn next_cards(card: usize, matching: usize) -> Vec<usize> {
+    (card + 1..card + matching + 1).collect()
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Prizes {
+    pub points: fn(usize) -> usize,
+    pub copies: fn(usize, usize) -> Vec<usize>,
+}
 
-    for (win, have) in winning.iter().zip(having.iter()) {
-        let matching = win.intersection(have).count();
-        if matching > 0 {
-            tot_pts += 2_usize.pow((matching - 1) as u32);
+impl Default for Prizes {
+    fn default() -> Prizes {
+        Prizes {
+            points: doubling_points,
+            copies: next_cards,
         }
     }
+}
 
-    tot_pts
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cascade {
+    // instances held of each card, originals included
+    pub copies: Vec<usize>,
+    // produced[i] lists the cards every instance of card i wins a copy of
+    pub produced: Vec<Vec<usize>>,
+    // longest chain of cards winning copies of later cards
+    pub depth: usize,
 }
 
-pub fn p2(lines: &Vec<String>) -> usize {
-    let (winning, having) = parse_lines(lines);
-    let mut tot_cards = 0;
-    let mut cur_copies = vec![1; having.len()];
+impl Cascade {
+    pub fn total_cards(&self) -> usize {
+        self.copies.iter().sum()
+    }
+
+    pub fn won_by(&self, card: usize) -> Vec<usize> {
+        (0..card)
+            .filter(|&src| self.produced[src].contains(&card))
+            .collect()
+    }
+}
+
+pub fn total_points(winning: &[HashSet<i32>], having: &[HashSet<i32>], prizes: &Prizes) -> usize {
+    winning
+        .iter()
+        .zip(having.iter())
+        .map(|(win, have)| (prizes.points)(count_matches(win, have)))
+        .sum()
+}
+
+// copies may only be won of later cards; anything else is dropped, as are cards past the table
+pub fn run_cascade(winning: &[HashSet<i32>], having: &[HashSet<i32>], prizes: &Prizes) -> Cascade {
+    let n = having.len();
+    let mut copies = vec![1; n];
+    let mut produced: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut chain = vec![0; n];
 
     for (i, (win, have)) in winning.iter().zip(having.iter()).enumerate() {
-        let cur_cnt = cur_copies[i];
-        tot_cards += cur_cnt;
-
-        let matching = win.intersection(have).count();
-        for j in 1..matching + 1 {
-            let k = i + j;
-            if k < cur_copies.len() {
-                cur_copies[k] += cur_cnt;
-            }
+        let cur_cnt = copies[i];
+
+        produced[i] = (prizes.copies)(i, count_matches(win, have))
+            .into_iter()
+            .filter(|&k| k > i && k < n)
+            .collect();
+
+        for &k in produced[i].iter() {
+            copies[k] += cur_cnt;
+            chain[k] = chain[k].max(chain[i] + 1);
         }
     }
 
-    tot_cards
+    Cascade {
+        copies,
+        produced,
+        depth: chain.into_iter().max().unwrap_or(0),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamTotals {
+    pub cards_read: usize,
+    pub points: usize,
+    pub total_cards: usize,
+}
+
+// scores cards one line at a time using the `next_cards` copy rule; only the pending copies
+// of the next few cards are kept, so memory is bounded by the largest match count seen
+pub fn score_stream<R: BufRead>(reader: R, points: fn(usize) -> usize) -> StreamTotals {
+    let mut totals = StreamTotals::default();
+    let mut pending: VecDeque<usize> = VecDeque::new();
+
+    for line in reader.lines() {
+        let line = line.expect("Could not parse line");
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (win, have) = parse_card(&line);
+        let matching = count_matches(&win, &have);
+        let cur_cnt = 1 + pending.pop_front().unwrap_or(0);
+
+        if pending.len() < matching {
+            pending.resize(matching, 0);
+        }
+        for extra in pending.iter_mut().take(matching) {
+            *extra += cur_cnt;
+        }
+
+        totals.cards_read += 1;
+        totals.points += points(matching);
+        totals.total_cards += cur_cnt;
+    }
+
+    totals
+}
+
+pub fn p1(lines: &[String]) -> usize {
+    let (winning, having) = parse_lines(lines);
+    total_points(&winning, &having, &Prizes::default())
+}
+
+pub fn p2(lines: &[String]) -> usize {
+    let (winning, having) = parse_lines(lines);
+    run_cascade(&winning, &having, &Prizes::default()).total_cards()
 }
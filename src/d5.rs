@@ -2,143 +2,514 @@
 
 */
 
-use std::collections::HashMap;
-
-fn get_mapping() -> HashMap<&'static str, &'static str> {
-    HashMap::from([
-        ("seed-to-soil", "soil-to-fertilizer"),
-        ("soil-to-fertilizer", "fertilizer-to-water"),
-        ("fertilizer-to-water", "water-to-light"),
-        ("water-to-light", "light-to-temperature"),
-        ("light-to-temperature", "temperature-to-humidity"),
-        ("temperature-to-humidity", "humidity-to-location"),
-        ("humidity-to-location", "end"),
-    ])
-}
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
-fn parse_line(line: &str) -> Vec<i64> {
+fn parse_line(line: &str) -> Result<Vec<i64>, String> {
     line.split_whitespace()
-        .map(|x| x.parse::<i64>().unwrap())
-        .collect::<Vec<i64>>()
+        .map(|x| {
+            x.parse::<i64>()
+                .map_err(|_| format!("{} is not a number", x))
+        })
+        .collect()
+}
+
+type Entry = (i64, i64, i64);
+
+// piecewise-linear map over i64: segment k covers [starts[k].0, starts[k + 1].0) and adds
+// starts[k].1 to every value in it; the last segment runs up to i64::MAX
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RangeMap {
+    starts: Vec<(i64, i64)>,
 }
 
-fn parse_lines(lines: &Vec<String>) -> (Vec<i64>, HashMap<&str, Vec<(i64, i64, i64)>>) {
-    let mut ht: HashMap<&str, Vec<(i64, i64, i64)>> = HashMap::new();
+impl RangeMap {
+    pub fn identity() -> RangeMap {
+        RangeMap {
+            starts: vec![(i64::MIN, 0)],
+        }
+    }
+
+    // (dest, src, len) entries as they appear in the almanac; if entries overlap, the first wins
+    pub fn from_entries(entries: &[Entry]) -> RangeMap {
+        let mut bounds: Vec<i64> = vec![i64::MIN];
+        for &(_, src, len) in entries {
+            bounds.push(src);
+            bounds.push(src + len);
+        }
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let starts = bounds
+            .iter()
+            .map(|&start| {
+                let offset = entries
+                    .iter()
+                    .find(|&&(_, src, len)| start >= src && start < src + len)
+                    .map_or(0, |&(dest, src, _)| dest - src);
+                (start, offset)
+            })
+            .collect();
+
+        RangeMap::merged(starts)
+    }
+
+    fn merged(starts: Vec<(i64, i64)>) -> RangeMap {
+        let mut merged: Vec<(i64, i64)> = Vec::new();
+        for (start, offset) in starts {
+            if merged.last().is_none_or(|&(_, prev)| prev != offset) {
+                merged.push((start, offset));
+            }
+        }
+        RangeMap { starts: merged }
+    }
+
+    fn segment_idx(&self, val: i64) -> usize {
+        self.starts.partition_point(|&(start, _)| start <= val) - 1
+    }
+
+    fn segment_end(&self, idx: usize) -> i64 {
+        self.starts
+            .get(idx + 1)
+            .map_or(i64::MAX, |&(start, _)| start)
+    }
+
+    // (start, exclusive end, offset) of every segment
+    pub fn segments(&self) -> impl Iterator<Item = (i64, i64, i64)> + '_ {
+        (0..self.starts.len()).map(|k| (self.starts[k].0, self.segment_end(k), self.starts[k].1))
+    }
+
+    pub fn get(&self, val: i64) -> i64 {
+        val + self.starts[self.segment_idx(val)].1
+    }
+
+    // images of the inclusive range (start, end), one per segment it crosses
+    pub fn map_range(&self, (start, end): (i64, i64)) -> Vec<(i64, i64)> {
+        let mut mapped: Vec<(i64, i64)> = Vec::new();
+        let mut idx = self.segment_idx(start);
+        let mut cur = start;
 
-    let seeds: Vec<i64> = parse_line(lines.get(0).unwrap().split(':').nth(1).unwrap().trim());
+        while cur <= end {
+            let seg_last = end.min(self.segment_end(idx).saturating_sub(1));
+            let offset = self.starts[idx].1;
+            mapped.push((cur + offset, seg_last + offset));
 
-    let mut cur_key: &str = "";
+            if seg_last == i64::MAX {
+                break;
+            }
+            cur = seg_last + 1;
+            idx += 1;
+        }
+
+        mapped
+    }
 
-    lines.iter().skip(1).for_each(|line| {
-        if line.is_empty() {
-            return;
+    // the map applying self first and then other
+    pub fn then(&self, other: &RangeMap) -> RangeMap {
+        let mut starts: Vec<(i64, i64)> = Vec::new();
+
+        for (start, end, offset) in self.segments() {
+            let image = (start.saturating_add(offset), end.saturating_add(offset) - 1);
+            let mut cur = start;
+
+            for (img_start, img_end) in other.map_range(image) {
+                let total_offset = img_start - cur;
+                starts.push((cur, total_offset));
+                cur = (img_end - total_offset).saturating_add(1);
+            }
+        }
+
+        RangeMap::merged(starts)
+    }
+
+    // every inclusive source range landing inside the inclusive range (start, end), sorted
+    pub fn preimage(&self, (start, end): (i64, i64)) -> Vec<(i64, i64)> {
+        let mut sources: Vec<(i64, i64)> = Vec::new();
+
+        for (seg_start, seg_end, offset) in self.segments() {
+            let lo = start.max(seg_start.saturating_add(offset));
+            let hi = end.min(seg_end.saturating_add(offset) - 1);
+            if lo <= hi {
+                sources.push((lo - offset, hi - offset));
+            }
+        }
+
+        merge_ranges(sources)
+    }
+}
+
+impl fmt::Display for RangeMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (start, end, offset) in self.segments().filter(|&(_, _, offset)| offset != 0) {
+            writeln!(
+                f,
+                "[{}, {}) -> [{}, {})",
+                start,
+                end,
+                start + offset,
+                end + offset
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Almanac {
+    pub seeds: Vec<i64>,
+    // source category -> (destination category, map) for every `source-to-destination` section
+    pub maps: HashMap<String, Vec<(String, RangeMap)>>,
+}
+
+fn parse_header(line: &str) -> Result<(String, String), String> {
+    let name = line
+        .strip_suffix("map:")
+        .ok_or_else(|| format!("malformed section header: {}", line))?
+        .trim();
+    let (source, target) = name
+        .split_once("-to-")
+        .ok_or_else(|| format!("section {} is not of the form source-to-destination", name))?;
+
+    Ok((source.to_string(), target.to_string()))
+}
+
+pub fn parse_almanac(lines: &[String]) -> Result<Almanac, String> {
+    let seeds_line = lines.first().ok_or("empty almanac")?;
+    let seeds = parse_line(
+        seeds_line
+            .strip_prefix("seeds:")
+            .ok_or_else(|| format!("expected seeds, found: {}", seeds_line))?,
+    )?;
+
+    let mut sections: Vec<(String, String, Vec<Entry>)> = Vec::new();
+
+    for line in lines.iter().skip(1) {
+        if line.trim().is_empty() {
+            continue;
         }
 
         if line.contains(':') {
-            let mut split = line.split(":");
-            cur_key = split
-                .next()
-                .unwrap()
-                .trim()
-                .split_whitespace()
-                .next()
-                .unwrap();
+            let (source, target) = parse_header(line.trim())?;
+            if sections
+                .iter()
+                .any(|(s, t, _)| *s == source && *t == target)
+            {
+                return Err(format!("duplicate section {}-to-{}", source, target));
+            }
+            sections.push((source, target, Vec::new()));
         } else {
-            if !ht.contains_key(cur_key) {
-                ht.insert(cur_key, Vec::new());
+            let v = parse_line(line)?;
+            if v.len() != 3 {
+                return Err(format!("expected dest, src and len, found: {}", line));
             }
-
-            let v = parse_line(&line);
-            ht.get_mut(cur_key).unwrap().push((v[0], v[1], v[2]));
+            sections
+                .last_mut()
+                .ok_or_else(|| format!("entry outside of any section: {}", line))?
+                .2
+                .push((v[0], v[1], v[2]));
         }
-    });
+    }
 
-    (seeds, ht)
+    let mut maps: HashMap<String, Vec<(String, RangeMap)>> = HashMap::new();
+    for (source, target, entries) in sections {
+        maps.entry(source)
+            .or_default()
+            .push((target, RangeMap::from_entries(&entries)));
+    }
+
+    let almanac = Almanac { seeds, maps };
+    almanac.check_acyclic()?;
+    Ok(almanac)
 }
 
-pub fn p1(lines: &Vec<String>) -> i64 {
-    let (seeds, ht) = parse_lines(lines);
-    let mapping = get_mapping();
-
-    let mut cur_key = "seed-to-soil";
-    let mut cur_vals: Vec<i64> = seeds.clone();
-    let mut cur_mod: Vec<bool> = vec![false; cur_vals.len()];
-
-    while cur_key != "end" {
-        for &(start_dest, start_src, length) in ht.get(cur_key).unwrap() {
-            for i in 0..cur_vals.len() {
-                if !cur_mod[i] {
-                    let cv = *cur_vals.get(i).unwrap();
-                    if cv >= start_src && cv < start_src + length {
-                        cur_vals[i] = start_dest + (cv - start_src);
-                        cur_mod[i] = true;
+impl Almanac {
+    fn targets(&self, category: &str) -> impl Iterator<Item = &(String, RangeMap)> {
+        self.maps.get(category).into_iter().flatten()
+    }
+
+    fn check_acyclic(&self) -> Result<(), String> {
+        fn visit<'a>(
+            almanac: &'a Almanac,
+            category: &'a str,
+            on_path: &mut Vec<&'a str>,
+            done: &mut HashSet<&'a str>,
+        ) -> Result<(), String> {
+            if on_path.contains(&category) {
+                return Err(format!("categories form a cycle through {}", category));
+            }
+            if !done.insert(category) {
+                return Ok(());
+            }
+
+            on_path.push(category);
+            for (target, _) in almanac.targets(category) {
+                visit(almanac, target, on_path, done)?;
+            }
+            on_path.pop();
+
+            Ok(())
+        }
+
+        let mut done: HashSet<&str> = HashSet::new();
+        for source in self.maps.keys() {
+            visit(self, source, &mut Vec::new(), &mut done)?;
+        }
+
+        Ok(())
+    }
+
+    // the category every chain from source ends in, e.g. location for seed; every section has
+    // to be reachable from source and every chain has to end in that same category, so a
+    // misnamed header is an error rather than a shorter chain
+    pub fn final_category(&self, source: &str) -> Result<String, String> {
+        if !self.maps.contains_key(source) {
+            return Err(format!("no section starts from {}", source));
+        }
+
+        let mut ends: Vec<String> = Vec::new();
+        let mut stack: Vec<&str> = vec![source];
+        let mut seen: HashSet<&str> = HashSet::new();
+
+        while let Some(category) = stack.pop() {
+            if !seen.insert(category) {
+                continue;
+            }
+            if !self.maps.contains_key(category) {
+                ends.push(category.to_string());
+            }
+            stack.extend(self.targets(category).map(|(target, _)| target.as_str()));
+        }
+
+        let mut unreachable: Vec<String> = self
+            .maps
+            .iter()
+            .filter(|(category, _)| !seen.contains(category.as_str()))
+            .flat_map(|(category, targets)| {
+                targets
+                    .iter()
+                    .map(move |(target, _)| format!("{}-to-{}", category, target))
+            })
+            .collect();
+        if !unreachable.is_empty() {
+            unreachable.sort();
+            return Err(format!(
+                "sections {:?} cannot be reached from {}",
+                unreachable, source
+            ));
+        }
+
+        ends.sort();
+        match ends.len() {
+            1 => Ok(ends.pop().unwrap()),
+            _ => Err(format!(
+                "{} does not lead to a single final category: {:?}",
+                source, ends
+            )),
+        }
+    }
+
+    // composes every section from source to target; when the categories branch, all routes
+    // have to agree on the resulting map
+    pub fn map_between(&self, source: &str, target: &str) -> Result<RangeMap, String> {
+        fn compose<'a>(
+            almanac: &'a Almanac,
+            category: &'a str,
+            target: &str,
+            memo: &mut HashMap<&'a str, Option<RangeMap>>,
+        ) -> Result<Option<RangeMap>, String> {
+            if category == target {
+                return Ok(Some(RangeMap::identity()));
+            }
+            if let Some(map) = memo.get(category) {
+                return Ok(map.clone());
+            }
+
+            let mut composed: Option<RangeMap> = None;
+            for (next, map) in almanac.targets(category) {
+                if let Some(rest) = compose(almanac, next, target, memo)? {
+                    let route = map.then(&rest);
+                    match &composed {
+                        Some(prev) if *prev != route => {
+                            return Err(format!("routes from {} to {} disagree", category, target));
+                        }
+                        _ => composed = Some(route),
                     }
                 }
             }
+
+            memo.insert(category, composed.clone());
+            Ok(composed)
         }
 
-        cur_mod = cur_mod.iter().map(|_| false).collect::<Vec<bool>>();
-        cur_key = mapping.get(cur_key).unwrap();
+        compose(self, source, target, &mut HashMap::new())?
+            .ok_or_else(|| format!("no sections lead from {} to {}", source, target))
     }
 
-    cur_vals.iter().min().unwrap().clone()
-}
+    pub fn seed_map(&self) -> Result<RangeMap, String> {
+        self.map_between("seed", &self.final_category("seed")?)
+    }
 
-fn is_in_between(start: i64, end: i64, val: i64) -> bool {
-    val >= start && val <= end
-}
+    // the parts of the seed ranges whose location lies in the inclusive range `locations`
+    pub fn seeds_for_locations(&self, locations: (i64, i64)) -> Result<Vec<(i64, i64)>, String> {
+        let map = self.seed_map()?;
+        let seeds = intersect_ranges(
+            &merge_ranges(seed_ranges(&self.seeds)),
+            &map.preimage(locations),
+        );
 
-pub fn p2(lines: &Vec<String>) -> i64 {
-    let (seeds, ht) = parse_lines(lines);
-    let mapping = get_mapping();
-
-    let mut cur_key = "seed-to-soil";
-    let mut cur_ranges: Vec<(i64, i64, bool)> = Vec::new();
-
-    for i in (0..seeds.len() - 1).step_by(2) {
-        cur_ranges.push((seeds[i], seeds[i] + seeds[i + 1] - 1, false));
-    }
-
-    while cur_key != "end" {
-        for &(start_dest, start_src, length) in ht.get(cur_key).unwrap() {
-            for i in 0..cur_ranges.len() {
-                let (cur_start, cur_end, cur_mod) = *cur_ranges.get_mut(i).unwrap();
-                let end_src = start_src + length - 1;
-                let end_dst = start_dest + length - 1;
-
-                let map_offset = start_dest - start_src;
-
-                let map_start = cur_start + map_offset;
-                let map_end = cur_end + map_offset;
-
-                if !cur_mod {
-                    if is_in_between(start_src, end_src, cur_start)
-                        && is_in_between(start_src, end_src, cur_end)
-                    {
-                        cur_ranges[i].0 = map_start;
-                        cur_ranges[i].1 = map_end;
-                        cur_ranges[i].2 = true;
-                    } else if is_in_between(start_src, end_src, cur_start) {
-                        cur_ranges[i].0 = end_src + 1;
-                        cur_ranges.push((map_start, end_dst, true));
-                    } else if is_in_between(start_src, end_src, cur_end) {
-                        cur_ranges[i].1 = start_src - 1;
-                        cur_ranges.push((start_dest, map_end, true));
-                    }
+        for &range in seeds.iter() {
+            for (start, end) in map.map_range(range) {
+                if start < locations.0 || end > locations.1 {
+                    return Err(format!("seeds {:?} map outside of {:?}", range, locations));
                 }
             }
         }
 
-        for i in 0..cur_ranges.len() {
-            cur_ranges[i].2 = false;
+        Ok(seeds)
+    }
+
+    // the lowest location reachable from the seed ranges and the seeds that reach it
+    pub fn lowest_location_seeds(&self) -> Result<(i64, Vec<(i64, i64)>), String> {
+        let map = self.seed_map()?;
+        let lowest = seed_ranges(&self.seeds)
+            .iter()
+            .flat_map(|&range| map.map_range(range))
+            .map(|(start, _)| start)
+            .min()
+            .ok_or("no seed ranges")?;
+
+        Ok((lowest, self.seeds_for_locations((lowest, lowest))?))
+    }
+}
+
+fn merge_ranges(mut ranges: Vec<(i64, i64)>) -> Vec<(i64, i64)> {
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(i64, i64)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+fn intersect_ranges(a: &[(i64, i64)], b: &[(i64, i64)]) -> Vec<(i64, i64)> {
+    let mut common: Vec<(i64, i64)> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() && j < b.len() {
+        let lo = a[i].0.max(b[j].0);
+        let hi = a[i].1.min(b[j].1);
+        if lo <= hi {
+            common.push((lo, hi));
+        }
+
+        if a[i].1 < b[j].1 {
+            i += 1;
+        } else {
+            j += 1;
         }
-        cur_key = mapping.get(cur_key).unwrap();
     }
 
-    cur_ranges
+    common
+}
+
+fn seed_ranges(seeds: &[i64]) -> Vec<(i64, i64)> {
+    seeds
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[0] + pair[1] - 1))
+        .collect()
+}
+
+pub fn p1(lines: &[String]) -> i64 {
+    let almanac = parse_almanac(lines).unwrap_or_else(|e| panic!("{}", e));
+    let map = almanac.seed_map().unwrap_or_else(|e| panic!("{}", e));
+
+    almanac
+        .seeds
         .iter()
-        .map(|&(start, _, _)| start)
+        .map(|&seed| map.get(seed))
         .min()
         .unwrap()
-        .clone()
+}
+
+pub fn p2(lines: &[String]) -> i64 {
+    let almanac = parse_almanac(lines).unwrap_or_else(|e| panic!("{}", e));
+    let (lowest, _) = almanac
+        .lowest_location_seeds()
+        .unwrap_or_else(|e| panic!("{}", e));
+
+    lowest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &[&str] = &[
+        "seeds: 79 14 55 13",
+        "",
+        "seed-to-soil map:",
+        "50 98 2",
+        "52 50 48",
+        "",
+        "soil-to-fertilizer map:",
+        "0 15 37",
+        "37 52 2",
+        "39 0 15",
+        "",
+        "fertilizer-to-water map:",
+        "49 53 8",
+        "0 11 42",
+        "42 0 7",
+        "57 7 4",
+        "",
+        "water-to-light map:",
+        "88 18 7",
+        "18 25 70",
+        "",
+        "light-to-temperature map:",
+        "45 77 23",
+        "81 45 19",
+        "68 64 13",
+        "",
+        "temperature-to-humidity map:",
+        "0 69 1",
+        "1 0 69",
+        "",
+        "humidity-to-location map:",
+        "60 56 37",
+        "56 93 4",
+    ];
+
+    fn sample_with(from: &str, to: &str) -> Vec<String> {
+        SAMPLE.iter().map(|l| l.replace(from, to)).collect()
+    }
+
+    #[test]
+    fn sample_answers() {
+        let lines: Vec<String> = SAMPLE.iter().map(|l| l.to_string()).collect();
+        assert_eq!(p1(&lines), 35);
+        assert_eq!(p2(&lines), 46);
+    }
+
+    #[test]
+    fn misnamed_seed_section() {
+        let almanac = parse_almanac(&sample_with("seed-to-soil", "seeds-to-soil")).unwrap();
+        assert!(almanac.seed_map().is_err());
+        assert!(almanac.lowest_location_seeds().is_err());
+    }
+
+    #[test]
+    fn misnamed_middle_section() {
+        let lines = sample_with("fertilizer-to-water", "fertiliser-to-water");
+        let almanac = parse_almanac(&lines).unwrap();
+        assert!(almanac.final_category("seed").is_err());
+        assert!(almanac.seed_map().is_err());
+    }
 }
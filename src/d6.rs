@@ -1,75 +1,233 @@
-use std::cmp::Ordering;
+use num_bigint::BigUint;
 
-fn parse_line(line: &String) -> Vec<i64> {
+fn parse_line(line: &str) -> Vec<u128> {
     line.split(':')
         .nth(1)
         .unwrap()
         .split_whitespace()
-        .map(|x| x.trim().parse::<i64>().unwrap())
+        .map(|x| x.trim().parse::<u128>().unwrap())
         .collect()
 }
 
-fn concat_line(line: &String) -> i64 {
+fn concat_line(line: &str) -> BigUint {
     line.split(':')
         .nth(1)
         .unwrap()
         .split_whitespace()
-        .map(|s| s.to_string())
-        .fold(String::new(), |a, b| a + b.as_str())
+        .collect::<String>()
         .parse()
         .unwrap()
 }
 
-fn parse_lines(lines: &Vec<String>) -> (Vec<i64>, Vec<i64>) {
+fn parse_lines(lines: &[String]) -> (Vec<u128>, Vec<u128>) {
     (parse_line(&lines[0]), parse_line(&lines[1]))
 }
 
-fn concat_lines(lines: &Vec<String>) -> (i64, i64) {
+fn concat_lines(lines: &[String]) -> (BigUint, BigUint) {
     (concat_line(&lines[0]), concat_line(&lines[1]))
 }
 
-fn compute_num_ways(time: i64, dist: i64) -> i64 {
-    fn compute_dist(hold: i64, time: i64) -> i64 {
-        hold * (time - hold)
+fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
     }
 
-    let (mut left, mut mid, mut right) = (0, -1, time / 2);
+    let mut x = 1_u128 << (n.ilog2() / 2 + 1);
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+// inclusive range of hold times h with h * (time - h) > dist, if there is one
+pub fn winning_holds(time: u128, dist: u128) -> Option<(u128, u128)> {
+    let (Some(time_sq), Some(dist_4)) = (time.checked_mul(time), dist.checked_mul(4)) else {
+        let (lo, hi) = winning_holds_big(&BigUint::from(time), &BigUint::from(dist))?;
+        return Some((lo.try_into().unwrap(), hi.try_into().unwrap()));
+    };
+
+    if time_sq <= dist_4 {
+        return None;
+    }
+
+    // h * (time - h) never exceeds time^2 / 4, so this cannot overflow
+    let beats = |hold: u128| hold * (time - hold) > dist;
+
+    // the real roots are (time -+ sqrt(time^2 - 4 * dist)) / 2, so this is within a step of the answer
+    let mut lo = (time - isqrt(time_sq - dist_4)) / 2;
+    while lo <= time / 2 && !beats(lo) {
+        lo += 1;
+    }
+    if lo > time / 2 {
+        return None;
+    }
+    while lo > 0 && beats(lo - 1) {
+        lo -= 1;
+    }
+
+    Some((lo, time - lo))
+}
+
+pub fn winning_holds_big(time: &BigUint, dist: &BigUint) -> Option<(BigUint, BigUint)> {
+    let time_sq = time * time;
+    let dist_4 = dist * 4_u32;
+
+    if time_sq <= dist_4 {
+        return None;
+    }
+
+    let one = BigUint::from(1_u32);
+    let half = time / 2_u32;
+    let beats = |hold: &BigUint| hold * (time - hold) > *dist;
+
+    let mut lo = (time - (time_sq - dist_4).sqrt()) / 2_u32;
+    while lo <= half && !beats(&lo) {
+        lo += 1_u32;
+    }
+    if lo > half {
+        return None;
+    }
+    while lo > BigUint::ZERO && beats(&(&lo - &one)) {
+        lo -= 1_u32;
+    }
+
+    let hi = time - &lo;
+    Some((lo, hi))
+}
 
-    while left <= right {
-        mid = left + (right - left) / 2;
-        match compute_dist(mid, time).cmp(&dist) {
-            Ordering::Less => left = mid + 1,
-            Ordering::Greater => right = mid - 1,
-            _ => break,
+// how holding the button charges the boat; every model gives distance 0 for hold > time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Charge {
+    // speed = k * hold
+    Linear { k: u128 },
+    // speed = min(k * hold, max_speed)
+    Capped { k: u128, max_speed: u128 },
+    // speed = k * hold, dropping by `decay` every millisecond of motion until the boat stops
+    Decay { k: u128, decay: u128 },
+}
+
+impl Charge {
+    pub fn distance(&self, hold: u128, time: u128) -> u128 {
+        if hold > time {
+            return 0;
+        }
+        let moving = time - hold;
+
+        match *self {
+            Charge::Linear { k } => k.saturating_mul(hold).saturating_mul(moving),
+            Charge::Capped { k, max_speed } => {
+                k.saturating_mul(hold).min(max_speed).saturating_mul(moving)
+            }
+            Charge::Decay { k, decay } => {
+                let speed = k.saturating_mul(hold);
+                if decay == 0 {
+                    return speed.saturating_mul(moving);
+                }
+
+                // speed + (speed - decay) + ... over `steps` milliseconds, all of them positive;
+                // past u128 the total is worked out exactly and then saturated like the others
+                let steps = moving.min(speed.div_ceil(decay));
+                let lost = |steps: u128| {
+                    let pairs = if steps.is_multiple_of(2) {
+                        (steps / 2).checked_mul(steps.saturating_sub(1))
+                    } else {
+                        steps.checked_mul((steps - 1) / 2)
+                    };
+                    pairs.and_then(|pairs| decay.checked_mul(pairs))
+                };
+
+                match (speed.checked_mul(steps), lost(steps)) {
+                    (Some(gained), Some(lost)) => gained - lost,
+                    _ => {
+                        let steps_big = BigUint::from(steps);
+                        let lost = BigUint::from(decay) * &steps_big * (&steps_big - 1_u32) / 2_u32;
+                        (BigUint::from(speed) * steps_big - lost)
+                            .try_into()
+                            .unwrap_or(u128::MAX)
+                    }
+                }
+            }
         }
     }
 
-    if compute_dist(mid, time) <= dist {
-        mid += 1
+    // the linear model reduces to the exact solver, everything else is searched
+    pub fn winning_holds(&self, time: u128, dist: u128) -> Option<(u128, u128)> {
+        match *self {
+            Charge::Linear { k: 0 } => None,
+            Charge::Linear { k } => winning_holds(time, dist / k),
+            _ => search_winning_holds(time, dist, |hold| self.distance(hold, time)),
+        }
     }
+}
 
-    let half_num_ways = time / 2 - mid;
-    let ways_near_end = if compute_dist(time / 2, time) == compute_dist(time / 2 + 1, time) {
-        2
-    } else {
-        1
-    };
+// binary searches for the peak of a distance function that rises and then falls over
+// 0..=time, then for the first and last holds on either side of it that beat dist
+pub fn search_winning_holds<F>(time: u128, dist: u128, distance: F) -> Option<(u128, u128)>
+where
+    F: Fn(u128) -> u128,
+{
+    let (mut left, mut right) = (0, time);
+    while left < right {
+        let mid = left + (right - left) / 2;
+        if distance(mid) < distance(mid + 1) {
+            left = mid + 1;
+        } else {
+            right = mid;
+        }
+    }
 
-    half_num_ways * 2 + ways_near_end
+    let peak = left;
+    if distance(peak) <= dist {
+        return None;
+    }
+
+    let (mut left, mut right) = (0, peak);
+    while left < right {
+        let mid = left + (right - left) / 2;
+        if distance(mid) > dist {
+            right = mid;
+        } else {
+            left = mid + 1;
+        }
+    }
+    let lo = left;
+
+    let (mut left, mut right) = (peak, time);
+    while left < right {
+        let mid = left + (right - left).div_ceil(2);
+        if distance(mid) > dist {
+            left = mid;
+        } else {
+            right = mid - 1;
+        }
+    }
+
+    Some((lo, left))
+}
+
+fn num_ways(holds: Option<(u128, u128)>) -> u128 {
+    holds.map_or(0, |(lo, hi)| hi - lo + 1)
 }
 
-pub fn p1(lines: &Vec<String>) -> i64 {
+pub fn p1(lines: &[String]) -> u128 {
     let (times, distances) = parse_lines(lines);
     let mut num_total_times = 1;
 
     for (&time, &dist) in times.iter().zip(distances.iter()) {
-        num_total_times *= compute_num_ways(time, dist);
+        num_total_times *= num_ways(Charge::Linear { k: 1 }.winning_holds(time, dist));
     }
 
     num_total_times
 }
 
-pub fn p2(lines: &Vec<String>) -> i64 {
+pub fn p2(lines: &[String]) -> BigUint {
     let (time, dist) = concat_lines(lines);
-    compute_num_ways(time, dist)
+
+    match (u128::try_from(&time), u128::try_from(&dist)) {
+        (Ok(time), Ok(dist)) => BigUint::from(num_ways(winning_holds(time, dist))),
+        _ => winning_holds_big(&time, &dist).map_or(BigUint::ZERO, |(lo, hi)| hi - lo + 1_u32),
+    }
 }
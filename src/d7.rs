@@ -1,20 +1,12 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::fmt;
 
-fn parse_lines(lines: &Vec<String>, consider_joker: bool) -> Vec<(String, usize)> {
+fn parse_lines(lines: &[String]) -> Vec<(String, usize)> {
     let mut hands: Vec<(String, usize)> = Vec::new();
 
     lines.iter().for_each(|line| {
         let mut split = line.split_whitespace();
-        let mut hand = split.next().unwrap().to_string();
-
-        if consider_joker {
-            hand = hand
-                .chars()
-                .map(|x| if x == 'J' { 'X' } else { x })
-                .collect();
-        }
-
+        let hand = split.next().unwrap().to_string();
         let bid = split.next().unwrap().parse::<usize>().unwrap();
         hands.push((hand, bid));
     });
@@ -22,83 +14,250 @@ fn parse_lines(lines: &Vec<String>, consider_joker: bool) -> Vec<(String, usize)
     hands
 }
 
-fn sort_hands_key(hand1: &String, hand2: &String) -> Ordering {
-    fn all_pos_cards() -> Vec<char> {
-        vec![
-            'A', 'K', 'Q', 'J', 'T', '9', '8', '7', '6', '5', '4', '3', '2',
-            'X', // X for Joker
-        ]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    // card groups of at least these sizes, largest first, e.g. [3, 2] for a full house
+    Groups(Vec<usize>),
+    // every card one step above the previous in the card order
+    Straight,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HandType {
+    pub name: String,
+    pub pattern: Pattern,
+}
+
+impl HandType {
+    pub fn new(name: &str, pattern: Pattern) -> HandType {
+        HandType {
+            name: name.to_string(),
+            pattern,
+        }
+    }
+}
+
+struct HandShape {
+    // sizes of the groups of non-wild cards, largest first
+    groups: Vec<usize>,
+    // strengths of the non-wild cards, ascending and without repeats
+    distinct: Vec<usize>,
+    wilds: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Ranking {
+    // weakest card first
+    pub card_order: Vec<char>,
+    // cards that stand in for whichever card makes the strongest hand type
+    pub wildcards: Vec<char>,
+    pub hand_size: usize,
+    // weakest hand type first; a hand takes the strongest type it can form
+    pub hand_types: Vec<HandType>,
+}
+
+impl Ranking {
+    pub fn camel_cards(jokers: bool) -> Ranking {
+        let (card_order, wildcards) = if jokers {
+            ("J23456789TQKA", vec!['J'])
+        } else {
+            ("23456789TJQKA", vec![])
+        };
+
+        Ranking {
+            card_order: card_order.chars().collect(),
+            wildcards,
+            hand_size: 5,
+            hand_types: vec![
+                HandType::new("high card", Pattern::Groups(vec![1])),
+                HandType::new("one pair", Pattern::Groups(vec![2])),
+                HandType::new("two pair", Pattern::Groups(vec![2, 2])),
+                HandType::new("three of a kind", Pattern::Groups(vec![3])),
+                HandType::new("full house", Pattern::Groups(vec![3, 2])),
+                HandType::new("four of a kind", Pattern::Groups(vec![4])),
+                HandType::new("five of a kind", Pattern::Groups(vec![5])),
+            ],
+        }
     }
 
-    fn get_card_strength(card: char) -> usize {
-        all_pos_cards()
+    // poker hand types that don't depend on suits
+    pub fn poker() -> Ranking {
+        let mut ranking = Ranking::camel_cards(false);
+        ranking
+            .hand_types
+            .insert(4, HandType::new("straight", Pattern::Straight));
+        ranking.hand_types.pop();
+        ranking
+    }
+
+    pub fn card_strength(&self, card: char) -> usize {
+        self.card_order
             .iter()
-            .rev()
             .position(|&c| c == card)
-            .unwrap()
+            .unwrap_or_else(|| panic!("{} is not a card", card))
     }
 
-    fn get_hand_type(hand: &String) -> usize {
-        let mut card_ht: HashMap<char, usize> = hand.chars().fold(HashMap::new(), |mut map, c| {
-            *map.entry(c).or_insert(0) += 1;
-            map
-        });
+    fn shape(&self, hand: &str) -> HandShape {
+        let mut counts = vec![0; self.card_order.len()];
+        let mut wilds = 0;
+
+        for card in hand.chars() {
+            if self.wildcards.contains(&card) {
+                wilds += 1;
+            } else {
+                counts[self.card_strength(card)] += 1;
+            }
+        }
 
-        let joker_count = card_ht.get(&'X').unwrap_or(&0).clone();
-        card_ht.remove(&'X');
-        let mx_count_before_joker = card_ht.values().max().unwrap_or(&0).clone();
+        let distinct: Vec<usize> = (0..counts.len()).filter(|&c| counts[c] > 0).collect();
+        let mut groups: Vec<usize> = counts.into_iter().filter(|&n| n > 0).collect();
+        groups.sort_unstable_by(|a, b| b.cmp(a));
 
-        if mx_count_before_joker == 0 {
-            return 6; // all jokers
+        HandShape {
+            groups,
+            distinct,
+            wilds,
         }
+    }
 
-        if joker_count > 0 {
-            for (_, count) in card_ht.iter_mut() {
-                if *count == mx_count_before_joker {
-                    *count += joker_count;
-                    break;
+    fn matches(&self, pattern: &Pattern, shape: &HandShape) -> bool {
+        match pattern {
+            Pattern::Groups(sizes) => {
+                // wildcards top up the largest groups first
+                let missing: usize = sizes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &size)| size.saturating_sub(*shape.groups.get(i).unwrap_or(&0)))
+                    .sum();
+                missing <= shape.wilds
+            }
+            Pattern::Straight => {
+                if self.hand_size > self.card_order.len() {
+                    return false;
                 }
+
+                let (Some(&lo), Some(&hi)) = (shape.distinct.first(), shape.distinct.last()) else {
+                    return true;
+                };
+                shape.groups.iter().all(|&n| n == 1) && hi - lo < self.hand_size
             }
         }
+    }
 
-        let card_counts: Vec<usize> = card_ht.values().cloned().collect();
-        let mx_count = card_counts.iter().max().unwrap().clone();
-
-        if mx_count >= 4 {
-            return mx_count + 1; // 5 for 4 of a kind, 6 for 5 of a kind
-        } else if card_counts.contains(&3) && card_counts.contains(&2) {
-            return 4; // 4 for full house
-        } else if mx_count == 3 {
-            return mx_count; // 3 for 3 of a kind
-        } else if card_counts.iter().filter(|&c| *c == 2).count() == 2 {
-            return 2; // 2 for two pair
-        } else {
-            return mx_count - 1; // 0 for high card, 1 for pair
+    pub fn hand_type(&self, hand: &str) -> usize {
+        assert_eq!(
+            hand.chars().count(),
+            self.hand_size,
+            "{} is not a hand of {} cards",
+            hand,
+            self.hand_size
+        );
+
+        let shape = self.shape(hand);
+        (0..self.hand_types.len())
+            .rev()
+            .find(|&idx| self.matches(&self.hand_types[idx].pattern, &shape))
+            .unwrap_or(0)
+    }
+
+    // the card each wildcard of the hand stands in for, in hand order
+    pub fn wild_assignment(&self, hand: &str) -> Vec<char> {
+        let hand_type = self.hand_type(hand);
+        let mut counts = vec![0; self.card_order.len()];
+        let mut wilds = 0;
+
+        for card in hand.chars() {
+            if self.wildcards.contains(&card) {
+                wilds += 1;
+            } else {
+                counts[self.card_strength(card)] += 1;
+            }
+        }
+
+        let mut assigned: Vec<usize> = Vec::new();
+        match &self.hand_types[hand_type].pattern {
+            Pattern::Groups(sizes) => {
+                // largest groups first, then stronger cards, so missing groups use the best free card
+                let mut by_group: Vec<usize> = (0..counts.len()).collect();
+                by_group.sort_by(|&a, &b| counts[b].cmp(&counts[a]).then(b.cmp(&a)));
+
+                for (&size, &card) in sizes.iter().zip(by_group.iter()) {
+                    for _ in counts[card]..size {
+                        assigned.push(card);
+                    }
+                }
+                while assigned.len() < wilds {
+                    assigned.push(by_group[0]);
+                }
+            }
+            Pattern::Straight => {
+                let lowest = (0..counts.len()).find(|&c| counts[c] > 0);
+                let top_start = self.card_order.len() - self.hand_size;
+                let start = lowest.map_or(top_start, |lo| lo.min(top_start));
+
+                assigned.extend((start..start + self.hand_size).filter(|&c| counts[c] == 0));
+            }
         }
+
+        assigned
+            .iter()
+            .take(wilds)
+            .map(|&c| self.card_order[c])
+            .collect()
     }
 
-    let hand_ordering = get_hand_type(hand1).cmp(&get_hand_type(hand2));
-    if !hand_ordering.is_eq() {
-        return hand_ordering;
+    fn bits_per_card(&self) -> u32 {
+        usize::BITS - self.card_order.len().leading_zeros()
     }
 
-    for (card1, card2) in hand1.chars().zip(hand2.chars()) {
-        let card_ordering = get_card_strength(card1).cmp(&get_card_strength(card2));
-        if !card_ordering.is_eq() {
-            return card_ordering;
-        }
+    // hand type in the high bits, then each card's strength in order, so keys sort like hands
+    pub fn hand_key(&self, hand: &str) -> u64 {
+        let bits = self.bits_per_card();
+        assert!(
+            bits * self.hand_size as u32 + usize::BITS - self.hand_types.len().leading_zeros()
+                <= u64::BITS,
+            "hands of {} cards don't fit in a u64 key",
+            self.hand_size
+        );
+
+        hand.chars().fold(self.hand_type(hand) as u64, |key, card| {
+            (key << bits) | self.card_strength(card) as u64
+        })
     }
 
-    Ordering::Equal
+    pub fn describe_key(&self, key: u64) -> String {
+        let bits = self.bits_per_card();
+        let mask = (1 << bits) - 1;
+
+        let cards: String = (0..self.hand_size)
+            .rev()
+            .map(|i| self.card_order[((key >> (bits * i as u32)) & mask) as usize])
+            .collect();
+        let hand_type = (key >> (bits * self.hand_size as u32)) as usize;
+
+        format!("{:#x} = {} {}", key, self.hand_types[hand_type].name, cards)
+    }
+
+    pub fn compare(&self, hand1: &str, hand2: &str) -> Ordering {
+        self.hand_key(hand1).cmp(&self.hand_key(hand2))
+    }
 }
 
-pub fn sort_hands(hands: &Vec<(String, usize)>) -> Vec<(String, usize)> {
-    let mut sorted_hands = hands.clone();
-    sorted_hands.sort_unstable_by(|hand1, hand2| sort_hands_key(&hand1.0, &hand2.0));
-    sorted_hands
+pub fn sort_hands(hands: &[(String, usize)], ranking: &Ranking) -> Vec<(String, usize)> {
+    let mut keyed_hands: Vec<(u64, usize)> = hands
+        .iter()
+        .enumerate()
+        .map(|(idx, hand)| (ranking.hand_key(&hand.0), idx))
+        .collect();
+    keyed_hands.sort_unstable();
+
+    keyed_hands
+        .iter()
+        .map(|&(_, idx)| hands[idx].clone())
+        .collect()
 }
 
-pub fn get_total_winnings(sorted_hands: &Vec<(String, usize)>) -> usize {
+pub fn get_total_winnings(sorted_hands: &[(String, usize)]) -> usize {
     let mut idx = 0;
     sorted_hands.iter().fold(0, |acc, hand| {
         idx += 1;
@@ -106,14 +265,94 @@ pub fn get_total_winnings(sorted_hands: &Vec<(String, usize)>) -> usize {
     })
 }
 
-pub fn p1(lines: &Vec<String>) -> usize {
-    let hands = parse_lines(lines, false);
-    let sorted_hands = sort_hands(&hands);
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HandReport {
+    pub hand: String,
+    pub bid: usize,
+    pub hand_type: String,
+    // (wildcard, card it was treated as) in hand order
+    pub wilds: Vec<(char, char)>,
+    pub rank: usize,
+    pub winnings: usize,
+}
+
+impl fmt::Display for HandReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rank {}: {} {}", self.rank, self.hand, self.hand_type)?;
+
+        if !self.wilds.is_empty() {
+            let treated_as: Vec<String> = self
+                .wilds
+                .iter()
+                .map(|(wild, card)| format!("{} as {}", wild, card))
+                .collect();
+            write!(
+                f,
+                " via {} {} ({})",
+                self.wilds.len(),
+                if self.wilds.len() == 1 {
+                    "wildcard"
+                } else {
+                    "wildcards"
+                },
+                treated_as.join(", ")
+            )?;
+        }
+
+        write!(f, ", bid {} wins {}", self.bid, self.winnings)
+    }
+}
+
+// one report per hand, weakest first; the winnings add up to get_total_winnings
+pub fn explain_hands(hands: &[(String, usize)], ranking: &Ranking) -> Vec<HandReport> {
+    sort_hands(hands, ranking)
+        .into_iter()
+        .enumerate()
+        .map(|(idx, (hand, bid))| {
+            let wilds = hand
+                .chars()
+                .filter(|c| ranking.wildcards.contains(c))
+                .zip(ranking.wild_assignment(&hand))
+                .collect();
+
+            HandReport {
+                hand_type: ranking.hand_types[ranking.hand_type(&hand)].name.clone(),
+                wilds,
+                rank: idx + 1,
+                winnings: (idx + 1) * bid,
+                hand,
+                bid,
+            }
+        })
+        .collect()
+}
+
+pub fn explain(lines: &[String]) -> String {
+    let hands = parse_lines(lines);
+    let mut out = String::new();
+
+    for (part, jokers) in [(1, false), (2, true)] {
+        let reports = explain_hands(&hands, &Ranking::camel_cards(jokers));
+        let total: usize = reports.iter().map(|report| report.winnings).sum();
+
+        out += &format!("part {}:\n", part);
+        for report in reports {
+            out += &format!("{}\n", report);
+        }
+        out += &format!("total winnings {}\n", total);
+    }
+
+    out
+}
+
+pub fn p1(lines: &[String]) -> usize {
+    let hands = parse_lines(lines);
+    let sorted_hands = sort_hands(&hands, &Ranking::camel_cards(false));
     get_total_winnings(&sorted_hands)
 }
 
-pub fn p2(lines: &Vec<String>) -> usize {
-    let hands = parse_lines(lines, true);
-    let sorted_hands = sort_hands(&hands);
+pub fn p2(lines: &[String]) -> usize {
+    let hands = parse_lines(lines);
+    let sorted_hands = sort_hands(&hands, &Ranking::camel_cards(true));
     get_total_winnings(&sorted_hands)
 }
@@ -1,116 +1,443 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
-fn parse_lines(lines: &Vec<String>) -> (Vec<char>, HashMap<String, (String, String)>) {
-    let instructions: Vec<char> = lines[0].clone().chars().collect();
-    let mut ht = HashMap::new();
+#[derive(Clone, Debug)]
+pub struct Network {
+    // 0 to take the left edge, 1 for the right
+    pub instructions: Vec<usize>,
+    pub names: Vec<String>,
+    pub ids: HashMap<String, usize>,
+    pub edges: Vec<[usize; 2]>,
+}
+
+impl Network {
+    fn intern(&mut self, name: &str) -> usize {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
 
-    for i in 2..lines.len() {
-        let split: Vec<String> = lines[i].split_whitespace().map(|s| s.to_string()).collect();
-        ht.insert(
-            split[0].clone(),
-            (split[2][1..4].to_string(), split[3][..3].to_string()),
-        );
+        let id = self.names.len();
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        self.edges.push([id, id]);
+        id
     }
 
-    return (instructions, ht);
-}
+    pub fn parse(lines: &[String]) -> Network {
+        let instructions = lines[0]
+            .trim()
+            .chars()
+            .map(|c| match c {
+                'L' => 0,
+                'R' => 1,
+                _ => panic!("Invalid instruction {}", c),
+            })
+            .collect::<Vec<usize>>();
+        if instructions.is_empty() {
+            panic!("No instructions on the first line");
+        }
 
-fn calculate_steps_p1(instructions: &Vec<char>, ht: &HashMap<String, (String, String)>) -> usize {
-    let mut steps = 0;
-    let mut current = String::from("AAA");
+        let mut network = Network {
+            instructions,
+            names: Vec::new(),
+            ids: HashMap::new(),
+            edges: Vec::new(),
+        };
+        let mut defined: HashSet<usize> = HashSet::new();
 
-    let mut instr_ptr = 0;
-    let n = instructions.len();
+        for line in lines.iter().skip(1).filter(|l| !l.trim().is_empty()) {
+            let (name, targets) = line
+                .split_once('=')
+                .unwrap_or_else(|| panic!("Invalid node {}", line));
+            let (left, right) = targets
+                .trim()
+                .trim_start_matches('(')
+                .trim_end_matches(')')
+                .split_once(',')
+                .unwrap_or_else(|| panic!("Invalid node {}", line));
 
-    while !current.eq(&String::from("ZZZ")) {
-        let (left, right) = ht.get(&current).unwrap();
+            let node = network.intern(name.trim());
+            let edges = [network.intern(left.trim()), network.intern(right.trim())];
+            network.edges[node] = edges;
+            if !defined.insert(node) {
+                panic!("Node {} is defined twice", name.trim());
+            }
+        }
 
-        if instructions[instr_ptr] == 'L' {
-            current = left.clone();
-        } else {
-            current = right.clone();
+        if let Some(missing) = (0..network.names.len()).find(|id| !defined.contains(id)) {
+            panic!("Node {} is never defined", network.names[missing]);
         }
 
-        instr_ptr = (instr_ptr + 1) % n;
-        steps += 1;
+        network
     }
 
-    steps
-}
+    pub fn id(&self, name: &str) -> usize {
+        *self
+            .ids
+            .get(name)
+            .unwrap_or_else(|| panic!("No node named {}", name))
+    }
 
-fn gcd(a: usize, b: usize) -> usize {
-    if b == 0 {
-        return a;
+    pub fn nodes_ending_with(&self, suffix: char) -> Vec<usize> {
+        (0..self.names.len())
+            .filter(|&id| self.names[id].ends_with(suffix))
+            .collect()
+    }
+
+    // the node reached from `node` when following the instruction at `instr_ptr`
+    pub fn step(&self, node: usize, instr_ptr: usize) -> usize {
+        self.edges[node][self.instructions[instr_ptr]]
+    }
+
+    // walks from start until a (node, instruction) state repeats, noting every step count
+    // at which the walk stands on an end node
+    pub fn ghost_cycle(&self, start: usize, is_end: &dyn Fn(usize) -> bool) -> GhostCycle {
+        let n = self.instructions.len();
+        let mut visited: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut end_steps: Vec<usize> = Vec::new();
+        let mut current = start;
+        let mut steps = 0;
+
+        loop {
+            if let Some(&cycle_start) = visited.get(&(current, steps % n)) {
+                let (tail_hits, cycle_hits) = end_steps.iter().partition(|&&s| s < cycle_start);
+                return GhostCycle {
+                    tail_hits,
+                    cycle_start,
+                    cycle_len: steps - cycle_start,
+                    cycle_hits,
+                };
+            }
+            visited.insert((current, steps % n), steps);
+
+            if is_end(current) {
+                end_steps.push(steps);
+            }
+
+            current = self.step(current, steps % n);
+            steps += 1;
+        }
+    }
+
+    // fewest steps after which every walk stands on an end node at the same time
+    pub fn steps_until_all(
+        &self,
+        starts: &[usize],
+        is_end: &dyn Fn(usize) -> bool,
+    ) -> Option<usize> {
+        let cycles: Vec<GhostCycle> = starts
+            .iter()
+            .map(|&start| self.ghost_cycle(start, is_end))
+            .collect();
+        let all_cycling = cycles.iter().map(|c| c.cycle_start).max()?;
+
+        // before every walk is inside its cycle, try each time the first walk hits an end
+        let first = &cycles[0];
+        let early = first
+            .tail_hits
+            .iter()
+            .copied()
+            .chain(
+                first
+                    .cycle_hits
+                    .iter()
+                    .flat_map(|&hit| (hit..all_cycling).step_by(first.cycle_len)),
+            )
+            .filter(|&steps| steps < all_cycling)
+            .filter(|&steps| cycles.iter().all(|c| c.is_hit(steps)));
+        if let Some(steps) = early.min() {
+            return Some(steps);
+        }
+
+        // afterwards each walk hits on fixed residues of its cycle length
+        let mut residues: Vec<(i128, i128)> = vec![(0, 1)];
+        for cycle in cycles.iter() {
+            let m = cycle.cycle_len as i128;
+            residues = residues
+                .iter()
+                .flat_map(|&(r, modulus)| {
+                    cycle
+                        .cycle_hits
+                        .iter()
+                        .filter_map(move |&hit| crt((r, modulus), (hit as i128 % m, m)))
+                })
+                .collect();
+        }
+
+        residues
+            .iter()
+            .map(|&(r, modulus)| {
+                let lo = all_cycling as i128;
+                if r >= lo {
+                    r
+                } else {
+                    r + (lo - r + modulus - 1) / modulus * modulus
+                }
+            })
+            .min()
+            .map(|steps| steps as usize)
     }
-    gcd(b, a % b)
 }
 
-fn lcm(nums: Vec<usize>) -> usize {
-    let mut lcm = nums[0];
-    for i in 1..nums.len() {
-        lcm = lcm * nums[i] / gcd(lcm, nums[i]);
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GhostCycle {
+    // steps at which an end node is reached before the walk enters its cycle
+    pub tail_hits: Vec<usize>,
+    pub cycle_start: usize,
+    pub cycle_len: usize,
+    // steps within the first pass of the cycle at which an end node is reached
+    pub cycle_hits: Vec<usize>,
+}
+
+impl GhostCycle {
+    pub fn lcm_period(&self) -> Option<usize> {
+        let period = *self.tail_hits.first().or(self.cycle_hits.first())?;
+        if period == 0 || !self.cycle_len.is_multiple_of(period) {
+            return None;
+        }
+
+        (1..self.cycle_start + self.cycle_len)
+            .all(|steps| self.is_hit(steps) == steps.is_multiple_of(period))
+            .then_some(period)
+    }
+
+    pub fn is_hit(&self, steps: usize) -> bool {
+        if steps < self.cycle_start {
+            return self.tail_hits.contains(&steps);
+        }
+
+        let offset = (steps - self.cycle_start) % self.cycle_len;
+        self.cycle_hits.contains(&(self.cycle_start + offset))
     }
-    lcm
 }
 
-fn calculate_steps_p2(instructions: &Vec<char>, ht: &HashMap<String, (String, String)>) -> usize {
-    let all_loops = ht
-        .keys()
-        .filter(|k| k.ends_with('A'))
-        .map(|s| compute_loops(instructions, ht, s.clone()))
-        .collect::<Vec<usize>>();
-    lcm(all_loops)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GhostReport {
+    pub start: String,
+    pub ends_reached: Vec<String>,
+    pub tail_len: usize,
+    pub cycle_len: usize,
+    pub end_steps: Vec<usize>,
+    // the period p when end nodes are hit exactly at steps p, 2p, 3p, ..., which is what
+    // the LCM shortcut assumes
+    pub lcm_period: Option<usize>,
 }
 
-fn compute_loops(
-    instructions: &Vec<char>,
-    ht: &HashMap<String, (String, String)>,
-    start: String,
-) -> usize {
-    let mut steps = 0;
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NetworkReport {
+    // strongly connected components, largest first
+    pub components: Vec<Vec<String>>,
+    pub ghosts: Vec<GhostReport>,
+    // nodes no start node can reach by any sequence of turns
+    pub unreachable: Vec<String>,
+}
 
-    let mut instr_ptr = 0;
-    let n = instructions.len();
+impl fmt::Display for NetworkReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} strongly connected components", self.components.len())?;
+        for component in self.components.iter().filter(|c| c.len() > 1) {
+            writeln!(f, "  {} nodes: {}", component.len(), component.join(" "))?;
+        }
 
-    let mut visited: HashMap<(String, usize), usize> = HashMap::new();
-    let mut current = start.clone();
-    let mut list_end_steps: Vec<usize> = Vec::new();
+        for ghost in self.ghosts.iter() {
+            writeln!(
+                f,
+                "{} reaches [{}], tail {}, cycle {}, ends at steps {:?}{}",
+                ghost.start,
+                ghost.ends_reached.join(" "),
+                ghost.tail_len,
+                ghost.cycle_len,
+                ghost.end_steps,
+                ghost
+                    .lcm_period
+                    .map_or(" (breaks LCM)".to_string(), |p| format!(" (period {})", p))
+            )?;
+        }
 
-    loop {
-        let (left, right) = ht.get(&current).unwrap();
-        steps += 1;
+        writeln!(f, "unreachable: [{}]", self.unreachable.join(" "))
+    }
+}
+
+impl Network {
+    pub fn to_dot(&self) -> String {
+        // names go between double quotes, where only '"' and '\' need escaping
+        let quoted = |node: usize| {
+            format!(
+                "\"{}\"",
+                self.names[node].replace('\\', "\\\\").replace('"', "\\\"")
+            )
+        };
+        let mut dot = String::from("digraph network {\n");
+
+        for node in 0..self.names.len() {
+            let [left, right] = self.edges[node];
+            dot += &format!("    {} -> {} [label=\"L\"];\n", quoted(node), quoted(left));
+            dot += &format!("    {} -> {} [label=\"R\"];\n", quoted(node), quoted(right));
+        }
+
+        dot + "}\n"
+    }
+
+    fn reachable_from(&self, starts: &[usize]) -> Vec<bool> {
+        let mut seen = vec![false; self.names.len()];
+        let mut stack: Vec<usize> = starts.to_vec();
 
-        match instructions[instr_ptr] {
-            'L' => {
-                current = left.clone();
+        while let Some(node) = stack.pop() {
+            if !seen[node] {
+                seen[node] = true;
+                stack.extend(self.edges[node]);
             }
-            'R' => {
-                current = right.clone();
+        }
+
+        seen
+    }
+
+    // Kosaraju's algorithm: finishing order on the graph, then sweeps on the reversed graph
+    pub fn strongly_connected_components(&self) -> Vec<Vec<usize>> {
+        let n = self.names.len();
+        let mut order: Vec<usize> = Vec::new();
+        let mut seen = vec![false; n];
+
+        for root in 0..n {
+            if seen[root] {
+                continue;
+            }
+            seen[root] = true;
+            let mut stack: Vec<(usize, usize)> = vec![(root, 0)];
+
+            while let Some((node, edge)) = stack.pop() {
+                if edge < 2 {
+                    stack.push((node, edge + 1));
+                    let next = self.edges[node][edge];
+                    if !seen[next] {
+                        seen[next] = true;
+                        stack.push((next, 0));
+                    }
+                } else {
+                    order.push(node);
+                }
             }
-            _ => panic!("Invalid instruction"),
         }
 
-        if current.ends_with('Z') {
-            list_end_steps.push(steps);
+        let mut reversed: Vec<Vec<usize>> = vec![Vec::new(); n];
+        for node in 0..n {
+            for next in self.edges[node] {
+                reversed[next].push(node);
+            }
+        }
+
+        let mut component_of: Vec<Option<usize>> = vec![None; n];
+        let mut components: Vec<Vec<usize>> = Vec::new();
+
+        for &root in order.iter().rev() {
+            if component_of[root].is_some() {
+                continue;
+            }
+
+            let mut component: Vec<usize> = Vec::new();
+            let mut stack: Vec<usize> = vec![root];
+            component_of[root] = Some(components.len());
+
+            while let Some(node) = stack.pop() {
+                component.push(node);
+                for &prev in reversed[node].iter() {
+                    if component_of[prev].is_none() {
+                        component_of[prev] = Some(components.len());
+                        stack.push(prev);
+                    }
+                }
+            }
+
+            components.push(component);
         }
 
-        let cur_state = (current.clone(), instr_ptr);
+        components.sort_by_key(|c| std::cmp::Reverse(c.len()));
+        components
+    }
+
+    pub fn analyze(&self, starts: &[usize], is_end: &dyn Fn(usize) -> bool) -> NetworkReport {
+        let to_names = |nodes: &[usize]| -> Vec<String> {
+            nodes.iter().map(|&node| self.names[node].clone()).collect()
+        };
+
+        let ghosts = starts
+            .iter()
+            .map(|&start| {
+                let cycle = self.ghost_cycle(start, is_end);
+                let mut ends_reached: Vec<usize> = Vec::new();
+                let mut current = start;
+
+                for steps in 0..cycle.cycle_start + cycle.cycle_len {
+                    if is_end(current) && !ends_reached.contains(&current) {
+                        ends_reached.push(current);
+                    }
+                    current = self.step(current, steps % self.instructions.len());
+                }
+
+                GhostReport {
+                    start: self.names[start].clone(),
+                    ends_reached: to_names(&ends_reached),
+                    tail_len: cycle.cycle_start,
+                    cycle_len: cycle.cycle_len,
+                    end_steps: [cycle.tail_hits.clone(), cycle.cycle_hits.clone()].concat(),
+                    lcm_period: cycle.lcm_period(),
+                }
+            })
+            .collect();
+
+        let reachable = self.reachable_from(starts);
+        let unreachable: Vec<usize> = (0..self.names.len()).filter(|&n| !reachable[n]).collect();
 
-        if visited.contains_key(&cur_state) {
-            return list_end_steps[0];
+        NetworkReport {
+            components: self
+                .strongly_connected_components()
+                .iter()
+                .map(|c| to_names(c))
+                .collect(),
+            ghosts,
+            unreachable: to_names(&unreachable),
         }
+    }
+}
 
-        visited.insert(cur_state, steps);
-        instr_ptr = (instr_ptr + 1) % n;
+fn ext_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        return (a, 1, 0);
     }
+    let (g, x, y) = ext_gcd(b, a % b);
+    (g, y, x - (a / b) * y)
 }
 
-pub fn p1(lines: &Vec<String>) -> usize {
-    let (instructions, ht) = parse_lines(lines);
-    calculate_steps_p1(&instructions, &ht)
+// x = r1 (mod m1) and x = r2 (mod m2) as a single residue, moduli need not be coprime
+fn crt((r1, m1): (i128, i128), (r2, m2): (i128, i128)) -> Option<(i128, i128)> {
+    let (g, p, _) = ext_gcd(m1, m2);
+    if (r2 - r1) % g != 0 {
+        return None;
+    }
+
+    let lcm = m1 / g * m2;
+    let k = ((r2 - r1) / g * p).rem_euclid(m2 / g);
+    Some(((r1 + m1 * k).rem_euclid(lcm), lcm))
 }
 
-pub fn p2(lines: &Vec<String>) -> usize {
-    let (instructions, ht) = parse_lines(lines);
-    calculate_steps_p2(&instructions, &ht)
+pub fn p1(lines: &[String]) -> usize {
+    let network = Network::parse(lines);
+    let end = network.id("ZZZ");
+
+    network
+        .steps_until_all(&[network.id("AAA")], &|node| node == end)
+        .expect("ZZZ is never reached")
+}
+
+pub fn p2(lines: &[String]) -> usize {
+    let network = Network::parse(lines);
+    let ends: HashSet<usize> = network.nodes_ending_with('Z').into_iter().collect();
+
+    network
+        .steps_until_all(&network.nodes_ending_with('A'), &|node| {
+            ends.contains(&node)
+        })
+        .expect("The ghosts never stand on Z nodes together")
 }
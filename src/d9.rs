@@ -1,53 +1,253 @@
-fn parse_lines(lines: &Vec<String>) -> Vec<Vec<i32>> {
-    let mut res: Vec<Vec<i32>> = Vec::new();
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use std::fmt;
+
+fn parse_lines(lines: &[String]) -> Vec<Vec<i128>> {
+    let mut res: Vec<Vec<i128>> = Vec::new();
     for line in lines {
-        let mut v: Vec<i32> = Vec::new();
+        let mut v: Vec<i128> = Vec::new();
         for num in line.split_whitespace() {
-            v.push(num.parse::<i32>().unwrap());
+            v.push(num.parse::<i128>().unwrap());
         }
         res.push(v);
     }
     res
 }
 
-pub fn get_next_num(v: &Vec<i32>, rev: bool) -> i32 {
-    let mut list_seq: Vec<Vec<i32>> = Vec::new();
-    list_seq.push(v.clone());
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Polynomial {
+    // first value of each row of the difference table, i.e. f(0), Δf(0), Δ²f(0), ...
+    pub forward_diffs: Vec<i128>,
+    // number of values the polynomial was fitted to, indexed from 0
+    pub len: usize,
+}
+
+impl Polynomial {
+    // builds the difference table down to a row of zeros; that row has to keep at least one
+    // value, otherwise the sequence is too short to show it is polynomial
+    pub fn fit(v: &[i128]) -> Result<Polynomial, String> {
+        let mut forward_diffs: Vec<i128> = Vec::new();
+        let mut cur_seq: Vec<i128> = v.to_vec();
+
+        while !cur_seq.iter().all(|x| *x == 0) {
+            if cur_seq.len() < 2 {
+                return Err(format!(
+                    "the differences of {:?} run out before reaching a row of zeros",
+                    v
+                ));
+            }
+
+            forward_diffs.push(cur_seq[0]);
+            cur_seq = cur_seq.windows(2).map(|w| w[1] - w[0]).collect();
+        }
+
+        if cur_seq.is_empty() {
+            return Err("cannot fit an empty sequence".to_string());
+        }
+
+        Ok(Polynomial {
+            forward_diffs,
+            len: v.len(),
+        })
+    }
+
+    // the zero sequence counts as degree 0
+    pub fn degree(&self) -> usize {
+        self.forward_diffs.len().saturating_sub(1)
+    }
+
+    // Newton's forward formula, f(x) = sum of C(x, k) Δᵏf(0), which holds for negative x too;
+    // None if an intermediate value overflows
+    pub fn eval(&self, x: i128) -> Option<i128> {
+        let mut binom: i128 = 1;
+        let mut total: i128 = 0;
+
+        for (k, &diff) in self.forward_diffs.iter().enumerate() {
+            if k > 0 {
+                let k = k as i128;
+                // C(x, k) = C(x, k - 1) * (x - k + 1) / k divides exactly
+                binom = binom.checked_mul(x - k + 1)? / k;
+            }
+            total = total.checked_add(binom.checked_mul(diff)?)?;
+        }
+
+        Some(total)
+    }
+
+    pub fn extrapolate(&self, direction: Direction, steps: usize) -> Option<i128> {
+        let steps = steps as i128;
+        match direction {
+            Direction::Forward => self.eval(self.len as i128 - 1 + steps),
+            Direction::Backward => self.eval(-steps),
+        }
+    }
+}
+
+fn rational(x: i128) -> BigRational {
+    BigRational::from_integer(BigInt::from(x))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Model {
+    Polynomial(Polynomial),
+    // a(n) = first * ratio^n
+    Geometric {
+        first: BigRational,
+        ratio: BigRational,
+    },
+    // a(n) = coeffs[0] * a(n - 1) + coeffs[1] * a(n - 2) + ...
+    Recurrence {
+        coeffs: Vec<BigRational>,
+    },
+}
+
+impl Model {
+    // values needed to pin the model down, used to prefer the simplest fit
+    pub fn num_params(&self) -> usize {
+        match self {
+            Model::Polynomial(poly) => poly.forward_diffs.len(),
+            Model::Geometric { .. } => 2,
+            Model::Recurrence { coeffs } => 2 * coeffs.len(),
+        }
+    }
 
-    loop {
-        let mut cur_seq: Vec<i32> = Vec::new();
-        let cur_v = list_seq.last().unwrap();
-        for i in 1..cur_v.len() {
-            let diff = cur_v[i] - cur_v[i - 1];
-            cur_seq.push(diff);
+    pub fn next_value(&self, v: &[i128]) -> BigRational {
+        match self {
+            Model::Polynomial(poly) => rational(
+                poly.eval(v.len() as i128)
+                    .expect("extrapolated value overflows"),
+            ),
+            Model::Geometric { first, ratio } => (0..v.len()).fold(first.clone(), |x, _| x * ratio),
+            Model::Recurrence { coeffs } => coeffs
+                .iter()
+                .zip(v.iter().rev())
+                .map(|(c, &x)| c * rational(x))
+                .sum(),
         }
-        list_seq.push(cur_seq.clone());
-        if cur_seq.iter().all(|x| *x == 0) {
-            break;
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Model::Polynomial(poly) => write!(f, "polynomial of degree {}", poly.degree()),
+            Model::Geometric { first, ratio } => write!(f, "a(n) = {} * ({})^n", first, ratio),
+            Model::Recurrence { coeffs } => {
+                let terms: Vec<String> = coeffs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| format!("({}) * a(n - {})", c, i + 1))
+                    .collect();
+                write!(f, "a(n) = {}", terms.join(" + "))
+            }
         }
     }
+}
+
+fn fit_geometric(v: &[i128]) -> Option<Model> {
+    if v.len() < 3 || v[0] == 0 {
+        return None;
+    }
+
+    let ratio = rational(v[1]) / rational(v[0]);
+    v.windows(2)
+        .all(|w| rational(w[1]) == rational(w[0]) * &ratio)
+        .then(|| Model::Geometric {
+            first: rational(v[0]),
+            ratio,
+        })
+}
+
+// Berlekamp-Massey over the rationals finds the shortest linear recurrence generating v;
+// it only counts as a fit when v has a value beyond the 2L needed to determine it
+fn fit_recurrence(v: &[i128]) -> Option<Model> {
+    let zero = rational(0);
+    let mut conn: Vec<BigRational> = vec![rational(1)];
+    let mut prev_conn: Vec<BigRational> = vec![rational(1)];
+    let mut len = 0;
+    let mut shift = 1;
+    let mut prev_disc = rational(1);
 
-    let mut lst_or_fst: i32 = 0;
+    for n in 0..v.len() {
+        let disc: BigRational = (0..=len).map(|i| &conn[i] * rational(v[n - i])).sum();
 
-    if rev {
-        for cur_seq in list_seq.iter().rev() {
-            lst_or_fst = cur_seq.first().unwrap() - lst_or_fst;
+        if disc == zero {
+            shift += 1;
+            continue;
         }
-    } else {
-        for cur_seq in list_seq.iter() {
-            lst_or_fst += cur_seq.last().unwrap();
+
+        let scale = &disc / &prev_disc;
+        let mut next_conn = conn.clone();
+        if next_conn.len() < prev_conn.len() + shift {
+            next_conn.resize(prev_conn.len() + shift, zero.clone());
+        }
+        for (i, c) in prev_conn.iter().enumerate() {
+            next_conn[i + shift] -= &scale * c;
+        }
+
+        if 2 * len <= n {
+            prev_conn = std::mem::replace(&mut conn, next_conn);
+            len = n + 1 - len;
+            prev_disc = disc;
+            shift = 1;
+        } else {
+            conn = next_conn;
+            shift += 1;
         }
     }
 
-    lst_or_fst
+    // the zero sequence (len 0) is left to the polynomial fit
+    if len == 0 || v.len() <= 2 * len {
+        return None;
+    }
+
+    conn.resize(len + 1, zero);
+    Some(Model::Recurrence {
+        coeffs: conn[1..].iter().map(|c| -c).collect(),
+    })
+}
+
+// every model that fits v exactly, simplest first
+pub fn analyze(v: &[i128]) -> Vec<Model> {
+    let mut models: Vec<Model> = [
+        Polynomial::fit(v).ok().map(Model::Polynomial),
+        fit_geometric(v),
+        fit_recurrence(v),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    models.sort_by_key(|model| model.num_params());
+    models
+}
+
+pub fn best_model(v: &[i128]) -> Option<Model> {
+    analyze(v).into_iter().next()
+}
+
+fn extrapolate_all(lines: &[String], direction: Direction) -> i128 {
+    parse_lines(lines)
+        .iter()
+        .map(|v| {
+            Polynomial::fit(v)
+                .unwrap_or_else(|e| panic!("{}", e))
+                .extrapolate(direction, 1)
+                .expect("extrapolated value overflows")
+        })
+        .sum()
 }
 
-pub fn p1(lines: &Vec<String>) -> i32 {
-    let nums = parse_lines(lines);
-    nums.iter().map(|v| get_next_num(v, false)).sum()
+pub fn p1(lines: &[String]) -> i128 {
+    extrapolate_all(lines, Direction::Forward)
 }
 
-pub fn p2(lines: &Vec<String>) -> i32 {
-    let nums = parse_lines(lines);
-    nums.iter().map(|v| get_next_num(v, true)).sum()
+pub fn p2(lines: &[String]) -> i128 {
+    extrapolate_all(lines, Direction::Backward)
 }
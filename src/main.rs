@@ -4,8 +4,11 @@ use std::{
     io::{prelude::*, BufReader},
 };
 
-mod d22;
-use d22::{p1, p2};
+#[allow(dead_code)]
+mod d12;
+#[allow(dead_code)]
+mod d7;
+use d12::{p1, p2};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -36,6 +39,12 @@ fn main() {
         .map(|l| l.expect("Could not parse line"))
         .collect();
 
+    // d7 is always compiled so its hand report works whichever day is switched in above
+    if day_number == 7 && args.iter().any(|arg| arg == "--explain") {
+        print!("{}", d7::explain(&lines));
+        return;
+    }
+
     println!("{:?}", p1(&lines));
     println!("{:?}", p2(&lines));
 }